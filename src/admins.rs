@@ -0,0 +1,106 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "admin_role", rename_all = "lowercase")]
+pub enum AdminRole {
+    Owner,
+    Moderator,
+    Broadcaster,
+}
+
+/// What an admin command needs, roles are not ordered so they are checked per permission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Admins and schedules.
+    Manage,
+    /// Support tickets, users mapping and the toad catalog.
+    Moderate,
+    /// Campaigns and forced toads.
+    Broadcast,
+}
+
+impl AdminRole {
+    pub fn can(&self, permission: Permission) -> bool {
+        match self {
+            AdminRole::Owner => true,
+            AdminRole::Moderator => permission == Permission::Moderate,
+            AdminRole::Broadcaster => permission == Permission::Broadcast,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdminRole::Owner => "owner",
+            AdminRole::Moderator => "moderator",
+            AdminRole::Broadcaster => "broadcaster",
+        }
+    }
+}
+
+impl fmt::Display for AdminRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AdminRole {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "owner" => Ok(AdminRole::Owner),
+            "moderator" => Ok(AdminRole::Moderator),
+            "broadcaster" => Ok(AdminRole::Broadcaster),
+            _ => Err(anyhow!(
+                "Unknown role `{}`, use owner, moderator or broadcaster",
+                s
+            )),
+        }
+    }
+}
+
+/// Row of the `admins` table.
+#[derive(Debug, Clone)]
+pub struct Admin {
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub role: AdminRole,
+}
+
+impl Admin {
+    pub fn describe(&self) -> String {
+        match self.username {
+            Some(ref username) => format!("{} ({}): {}", username, self.user_id, self.role),
+            None => format!("{}: {}", self.user_id, self.role),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_grant_permissions() {
+        for permission in [
+            Permission::Manage,
+            Permission::Moderate,
+            Permission::Broadcast,
+        ] {
+            assert!(AdminRole::Owner.can(permission));
+        }
+        assert!(AdminRole::Moderator.can(Permission::Moderate));
+        assert!(!AdminRole::Moderator.can(Permission::Broadcast));
+        assert!(AdminRole::Broadcaster.can(Permission::Broadcast));
+        assert!(!AdminRole::Broadcaster.can(Permission::Manage));
+
+        assert_eq!(
+            "Moderator".parse::<AdminRole>().unwrap(),
+            AdminRole::Moderator
+        );
+        assert!("root".parse::<AdminRole>().is_err());
+    }
+}
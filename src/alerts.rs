@@ -0,0 +1,208 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::period::{format_period, parse_period};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "alert_kind", rename_all = "lowercase")]
+pub enum AlertKind {
+    Above,
+    Below,
+    Change,
+}
+
+/// Parsed `/alert` arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRequest {
+    pub coin: String,
+    pub kind: AlertKind,
+    pub threshold: f64,
+    pub window: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub id: i64,
+    pub chat_id: i64,
+    pub coin: String,
+    pub kind: AlertKind,
+    pub threshold: f64,
+    pub window_secs: Option<i64>,
+    pub reference_price: Option<f64>,
+    pub reference_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertCheck {
+    Fire,
+    Keep,
+    /// The window of a change alert is over, start a new one from the current price.
+    Rebase,
+}
+
+pub const ALERT_USAGE: &str = "Usage:\n\
+    /alert BTC > 100000 - price goes above\n\
+    /alert BTC < 50000 - price goes below\n\
+    /alert BTC 5% 1h - price moves by ±5% within an hour (m, h and d are supported)";
+
+const DEFAULT_WINDOW: i64 = 60 * 60;
+
+pub fn parse_alert(text: &str) -> Result<AlertRequest> {
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    let (coin, rest) = match parts.split_first() {
+        Some((coin, rest)) if !rest.is_empty() => (coin.to_uppercase(), rest),
+        _ => return Err(anyhow!("Not enough arguments")),
+    };
+
+    let parse_price = |value: &str| -> Result<f64> {
+        let price = value
+            .trim_end_matches('$')
+            .parse::<f64>()
+            .map_err(|_| anyhow!("Could not parse price `{}`", value))?;
+        if price.is_finite() && price > 0. {
+            Ok(price)
+        } else {
+            Err(anyhow!("Price should be positive"))
+        }
+    };
+
+    match rest {
+        [">", price] => Ok(AlertRequest {
+            coin,
+            kind: AlertKind::Above,
+            threshold: parse_price(price)?,
+            window: None,
+        }),
+        ["<", price] => Ok(AlertRequest {
+            coin,
+            kind: AlertKind::Below,
+            threshold: parse_price(price)?,
+            window: None,
+        }),
+        [percents, window @ ..] if percents.ends_with('%') && window.len() <= 1 => {
+            let percents = percents
+                .trim_start_matches('±')
+                .trim_start_matches("+-")
+                .trim_end_matches('%');
+            let threshold = percents
+                .parse::<f64>()
+                .map_err(|_| anyhow!("Could not parse percents `{}`", percents))?;
+            if !(threshold.is_finite() && threshold > 0.) {
+                return Err(anyhow!("Percents should be positive"));
+            }
+            let window = match window.first() {
+                Some(window) => parse_period(window)?,
+                None => Duration::seconds(DEFAULT_WINDOW),
+            };
+            Ok(AlertRequest {
+                coin,
+                kind: AlertKind::Change,
+                threshold,
+                window: Some(window),
+            })
+        }
+        _ => Err(anyhow!("Unknown alert condition")),
+    }
+}
+
+impl Alert {
+    pub fn check(&self, price: f64, now: DateTime<Utc>) -> AlertCheck {
+        match self.kind {
+            AlertKind::Above if price > self.threshold => AlertCheck::Fire,
+            AlertKind::Below if price < self.threshold => AlertCheck::Fire,
+            AlertKind::Above | AlertKind::Below => AlertCheck::Keep,
+            AlertKind::Change => {
+                let (reference_price, reference_at) =
+                    match (self.reference_price, self.reference_at) {
+                        (Some(price), Some(at)) => (price, at),
+                        _ => return AlertCheck::Rebase,
+                    };
+                if self.change(price).abs() >= self.threshold {
+                    AlertCheck::Fire
+                } else if now - reference_at
+                    >= Duration::seconds(self.window_secs.unwrap_or(DEFAULT_WINDOW))
+                    || reference_price <= 0.
+                {
+                    AlertCheck::Rebase
+                } else {
+                    AlertCheck::Keep
+                }
+            }
+        }
+    }
+
+    /// Change against the reference price in percents.
+    pub fn change(&self, price: f64) -> f64 {
+        match self.reference_price {
+            Some(reference) if reference > 0. => (price / reference - 1.) * 100.,
+            _ => 0.,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self.kind {
+            AlertKind::Above => format!("#{} {} > {}$", self.id, self.coin, self.threshold),
+            AlertKind::Below => format!("#{} {} < {}$", self.id, self.coin, self.threshold),
+            AlertKind::Change => format!(
+                "#{} {} ±{}% within {}",
+                self.id,
+                self.coin,
+                self.threshold,
+                format_period(Duration::seconds(
+                    self.window_secs.unwrap_or(DEFAULT_WINDOW)
+                ))
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_alert_conditions() {
+        let alert = parse_alert("btc > 100000").unwrap();
+        assert_eq!(alert.coin, "BTC");
+        assert_eq!(alert.kind, AlertKind::Above);
+        assert_eq!(alert.threshold, 100000.);
+
+        let alert = parse_alert("ETH < 1500.5$").unwrap();
+        assert_eq!(alert.kind, AlertKind::Below);
+        assert_eq!(alert.threshold, 1500.5);
+
+        let alert = parse_alert("TON ±5% 30m").unwrap();
+        assert_eq!(alert.kind, AlertKind::Change);
+        assert_eq!(alert.threshold, 5.);
+        assert_eq!(alert.window, Some(Duration::minutes(30)));
+
+        let alert = parse_alert("TON 2.5%").unwrap();
+        assert_eq!(alert.window, Some(Duration::hours(1)));
+
+        assert!(parse_alert("BTC").is_err());
+        assert!(parse_alert("BTC > -1").is_err());
+        assert!(parse_alert("BTC 5% 1w").is_err());
+        assert!(parse_alert("BTC = 5").is_err());
+    }
+
+    #[test]
+    fn change_alert_rebases_after_window() {
+        let now = Utc::now();
+        let alert = Alert {
+            id: 1,
+            chat_id: 1,
+            coin: "BTC".to_owned(),
+            kind: AlertKind::Change,
+            threshold: 5.,
+            window_secs: Some(3600),
+            reference_price: Some(100.),
+            reference_at: Some(now - Duration::minutes(30)),
+        };
+        assert_eq!(alert.check(104., now), AlertCheck::Keep);
+        assert_eq!(alert.check(94., now), AlertCheck::Fire);
+        assert_eq!(
+            alert.check(104., now + Duration::hours(1)),
+            AlertCheck::Rebase
+        );
+    }
+}
@@ -1,17 +1,30 @@
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
-use crate::cache::{Cache, CachePool};
-use crate::config::AdminUserId;
-use crate::database::{Database, Pool};
+use crate::admins::{Admin, AdminRole, Permission};
+use crate::alerts;
+use crate::cache::Cache;
+use crate::campaigns::{self, Campaign, CampaignStatus, Report};
+use crate::chart::{self, ChartKind, Resolution};
+use crate::coins::{Coin, CoinRegistry};
+use crate::config::{AdminUserId, Cfg};
+use crate::database::{Database, Pool, Topic};
+use crate::delivery::{Delivery, Outbox};
+use crate::members::{self, ChatMember};
+use crate::metrics;
+use crate::period::{format_period, parse_period};
 use crate::rates;
+use crate::scheduler::{CatchUp, JobSchedule, Scheduler};
+use crate::tickets::{Ticket, TicketStatus};
+use crate::toads;
 
 use anyhow::{anyhow, Error, Result};
+use futures::future::join_all;
 use futures::try_join;
 use rand::RngExt;
 use serde::Deserialize;
 use teloxide::dispatching::{DpHandlerDescription, UpdateFilterExt};
-use teloxide::types::InputFile;
+use teloxide::types::{FileId, InlineKeyboardButton, InlineKeyboardMarkup, InputFile};
 use teloxide::types::{
     InlineQueryResultArticle, InputMessageContent, InputMessageContentText, ParseMode::MarkdownV2,
 };
@@ -46,36 +59,36 @@ pub enum Command {
     NotToday,
     #[command(description = "show status of this crypto chat")]
     Stonks,
-    #[command(description = "show rates of BTC, ETH and BNB to USD")]
+    #[command(description = "list topics this chat is subscribed to")]
+    Subscriptions,
+    #[command(description = "show or set timezone of this chat, e.g. /timezone Europe/Berlin")]
+    Timezone(String),
+    #[command(description = "show or set toad delivery time of this chat, e.g. /toadtime 10:30")]
+    ToadTime(String),
+    #[command(description = "show rates of all known coins to USD")]
     Rates,
-    #[command(description = "show rate of BTC to USD")]
-    Btc,
-    #[command(description = "show rate of ETH to USD")]
-    Eth,
-    #[command(description = "show rate of LTC to USD")]
-    Ltc,
-    #[command(description = "show rate of ETC to USD")]
-    Etc,
-    #[command(description = "show rate of SOL to USD")]
-    Sol,
-    #[command(description = "show rate of ADA to USD")]
-    Ada,
-    #[command(description = "show rate of ZEE to USD")]
-    Zee,
-    #[command(description = "show rate of BNB to USD")]
-    Bnb,
-    #[command(description = "show rate of LUNA to USD")]
-    Luna,
-    #[command(description = "show rate of NOT to USD")]
-    Not,
-    #[command(description = "show rate of TON to USD")]
-    Ton,
+    #[command(description = "show rate of the coin to USD, e.g. /rate btc")]
+    Rate(String),
+    #[command(description = "show price of the coin some time ago, e.g. /history btc 7d")]
+    History(String),
+    #[command(
+        description = "draw price chart of the coin, e.g. /chart btc 7d or /chart btc 30d line"
+    )]
+    Chart(String),
+    #[command(description = "add price alert, e.g. /alert BTC > 100000")]
+    Alert(String),
+    #[command(description = "list price alerts of this chat")]
+    Alerts,
+    #[command(description = "remove price alert, e.g. /unalert 12")]
+    Unalert(String),
     #[command(description = "show BTC and ETH dominance")]
     Dominance,
     #[command(description = "show USD rate")]
     Usd,
     #[command(description = "cast all in members in chat")]
     All,
+    #[command(description = "stop mentioning you with /all in this chat, send again to undo")]
+    NoAll,
 }
 
 pub async fn commands_endpoint(
@@ -83,19 +96,14 @@ pub async fn commands_endpoint(
     msg: Message,
     command: Command,
     pool: Pool,
-    cache_pool: CachePool,
+    cache: Cache,
+    coins: CoinRegistry,
+    cfg: Arc<Cfg>,
 ) -> Result<()> {
     let db = Database::new(pool.clone()).await?;
 
-    update_users_mapping(&bot, &msg.from, pool.clone())
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to update users mapping: {}", e);
-            e
-        })
-        .ok();
-
     tracing::info!("received command {:?} from {:?}", command, msg.chat);
+    count_command(&msg);
 
     match command {
         Command::Help => {
@@ -103,50 +111,26 @@ pub async fn commands_endpoint(
                 .send()
                 .await?;
         }
-        Command::Start => {
-            on_start(bot, msg, db).await?;
-        }
-        Command::Stop => {
-            on_stop(bot, msg, db).await?;
-        }
-        Command::Status => {
-            on_status(bot, msg, db).await?;
-        }
-        Command::WhenLambo => on_crypto_start(bot, msg, db).await?,
-        Command::NotToday => on_crypto_stop(bot, msg, db).await?,
-        Command::Stonks => on_crypto_status(bot, msg, db).await?,
-        Command::Rates => on_rates(bot, msg).await?,
-        Command::Btc => {
-            on_coin_with_24hr_change(bot, msg, "BTC", &rates::get_btc_rate_with_24hr_change).await?
-        }
-        Command::Eth => {
-            on_coin_with_24hr_change(bot, msg, "ETH", &rates::get_eth_rate_with_24hr_change).await?
-        }
-        Command::Ltc => on_coin(bot, msg, "LTC", &rates::get_ltc_rate).await?,
-        Command::Etc => on_coin(bot, msg, "ETC", &rates::get_etc_rate).await?,
-        Command::Sol => {
-            on_coin_with_24hr_change(bot, msg, "SOL", &rates::get_sol_rate_with_24hr_change).await?
-        }
-        Command::Ada => on_coin(bot, msg, "ADA", &rates::get_ada_rate).await?,
-        Command::Zee => {
-            on_coin_with_24hr_change(bot, msg, "ZEE", &rates::get_zee_rate_with_24hr_change).await?
-        }
-        Command::Bnb => {
-            on_coin_with_24hr_change(bot, msg, "BNB", &rates::get_bnb_rate_with_24hr_change).await?
-        }
-        Command::Luna => {
-            on_coin_with_24hr_change(bot, msg, "LUNA", &rates::get_luna_rate_with_24hr_change)
-                .await?
-        }
-        Command::Not => {
-            on_coin_with_24hr_change(bot, msg, "NOT", &rates::get_not_rate_with_24hr_change).await?
-        }
-        Command::Ton => {
-            on_coin_with_24hr_change(bot, msg, "TON", &rates::get_ton_rate_with_24hr_change).await?
-        }
-        Command::Dominance => on_dominance(bot, msg, cache_pool.clone()).await?,
+        Command::Start => on_subscribe(bot, msg, db, Topic::Wednesday).await?,
+        Command::Stop => on_unsubscribe(bot, msg, db, Topic::Wednesday).await?,
+        Command::Status => on_subscription_status(bot, msg, db, Topic::Wednesday).await?,
+        Command::WhenLambo => on_subscribe(bot, msg, db, Topic::Crypto).await?,
+        Command::NotToday => on_unsubscribe(bot, msg, db, Topic::Crypto).await?,
+        Command::Stonks => on_subscription_status(bot, msg, db, Topic::Crypto).await?,
+        Command::Subscriptions => on_subscriptions(bot, msg, db).await?,
+        Command::Timezone(args) => on_timezone(bot, msg, db, &args).await?,
+        Command::ToadTime(args) => on_toad_time(bot, msg, db, &args).await?,
+        Command::Rates => on_rates(bot, msg, coins).await?,
+        Command::Rate(ticker) => on_rate(bot, msg, &ticker, coins).await?,
+        Command::History(args) => on_history(bot, msg, db, &args, coins).await?,
+        Command::Chart(args) => on_chart(bot, msg, db, &args, coins).await?,
+        Command::Alert(args) => on_alert(bot, msg, db, &args, coins).await?,
+        Command::Alerts => on_alerts(bot, msg, db).await?,
+        Command::Unalert(args) => on_unalert(bot, msg, db, &args).await?,
+        Command::Dominance => on_dominance(bot, msg, cache.clone(), &cfg).await?,
         Command::Usd => on_usd(bot, msg).await?,
-        Command::All => on_all(bot, msg).await?,
+        Command::All => on_all(bot, msg, db).await?,
+        Command::NoAll => on_no_all(bot, msg, db).await?,
     };
 
     Ok(())
@@ -162,6 +146,66 @@ pub enum AdminCommand {
     Mapping,
     #[command(description = "forced wednesday.")]
     Wednesday,
+    #[command(description = "show scheduled jobs and their next fire time.")]
+    Schedules,
+    #[command(
+        description = "change job schedule: /schedule <job> <timezone> <cron>, /schedule <job> on|off or /schedule <job> catchup skip|once|all."
+    )]
+    Schedule(String),
+    #[command(description = "add toad to the catalog: /addtoad <youtube link> [weight].")]
+    AddToad(String),
+    #[command(description = "change how often the toad is picked: /toadweight <id> <weight>.")]
+    ToadWeight(String),
+    #[command(description = "remove toad from the catalog: /rmtoad <id>.")]
+    RmToad(String),
+    #[command(description = "list toads in the catalog.")]
+    Toads,
+    #[command(description = "show recent broadcast campaigns.")]
+    Campaigns,
+    #[command(description = "list open support tickets.")]
+    Tickets,
+    #[command(description = "close support ticket: /close <id>.")]
+    Close(String),
+    #[command(description = "list admins and their roles.")]
+    Admins,
+    #[command(
+        description = "grant admin role: /grant <user id or @username> owner|moderator|broadcaster."
+    )]
+    Grant(String),
+    #[command(description = "revoke admin role: /revoke <user id or @username>.")]
+    Revoke(String),
+}
+
+impl AdminCommand {
+    fn permission(&self) -> Permission {
+        match self {
+            AdminCommand::Schedules
+            | AdminCommand::Schedule(_)
+            | AdminCommand::Admins
+            | AdminCommand::Grant(_)
+            | AdminCommand::Revoke(_) => Permission::Manage,
+            AdminCommand::Mapping
+            | AdminCommand::AddToad(_)
+            | AdminCommand::ToadWeight(_)
+            | AdminCommand::RmToad(_)
+            | AdminCommand::Toads
+            | AdminCommand::Tickets
+            | AdminCommand::Close(_) => Permission::Moderate,
+            AdminCommand::Wednesday | AdminCommand::Campaigns => Permission::Broadcast,
+        }
+    }
+}
+
+/// The owner from the config has every permission even with an empty `admins` table.
+pub async fn get_admin_role(
+    pool: Pool,
+    user_id: i64,
+    owner: AdminUserId,
+) -> Result<Option<AdminRole>> {
+    if user_id == owner.0 {
+        return Ok(Some(AdminRole::Owner));
+    }
+    Database::new(pool).await?.get_admin_role(user_id).await
 }
 
 #[tracing::instrument]
@@ -169,9 +213,23 @@ pub async fn admin_commands_endpoint(
     bot: Bot,
     msg: Message,
     command: AdminCommand,
+    role: AdminRole,
     pool: Pool,
+    outbox: Outbox,
+    owner: AdminUserId,
 ) -> Result<()> {
     let db = Database::new(pool.clone()).await?;
+    count_command(&msg);
+
+    if !role.can(command.permission()) {
+        bot.send_message(
+            msg.chat.id,
+            format!("⚠ The {} role is not allowed to do this", role),
+        )
+        .send()
+        .await?;
+        return Ok(());
+    }
 
     match command {
         AdminCommand::Mapping => {
@@ -184,165 +242,407 @@ pub async fn admin_commands_endpoint(
             bot.send_message(msg.chat.id, text).send().await?;
         }
         AdminCommand::Wednesday => {
-            let chats = db.get_all_active_chats().await?;
-            let url = crate::toads::get_toad();
-            for chat in chats {
-                bot.send_message(ChatId(chat), &url).send().await.ok();
-            }
+            let chats = db.get_toad_chats().await?;
+            let total = chats.len();
+            let outcomes = Scheduler::send_toads_to(&outbox, pool, chats).await?;
+            let sent = outcomes
+                .iter()
+                .filter(|(_, outcome)| outcome.is_sent())
+                .count();
+            let text = format!(
+                "Toads sent to {} chats, {} failed, {} got it this week already",
+                sent,
+                outcomes.len() - sent,
+                total - outcomes.len()
+            );
+            bot.send_message(msg.chat.id, text).send().await?;
         }
+        AdminCommand::Schedules => on_schedules(bot, msg, db).await?,
+        AdminCommand::Schedule(args) => on_schedule(bot, msg, db, &args).await?,
+        AdminCommand::AddToad(args) => on_add_toad(bot, msg, db, &args).await?,
+        AdminCommand::ToadWeight(args) => on_toad_weight(bot, msg, db, &args).await?,
+        AdminCommand::RmToad(args) => on_remove_toad(bot, msg, db, &args).await?,
+        AdminCommand::Toads => on_toads(bot, msg, db).await?,
+        AdminCommand::Campaigns => on_campaigns(bot, msg, db).await?,
+        AdminCommand::Tickets => on_tickets(bot, msg, db).await?,
+        AdminCommand::Close(args) => on_close_ticket(bot, msg, db, &args).await?,
+        AdminCommand::Admins => on_admins(bot, msg, db, owner).await?,
+        AdminCommand::Grant(args) => on_grant(bot, msg, db, &args, owner).await?,
+        AdminCommand::Revoke(args) => on_revoke(bot, msg, db, &args, owner).await?,
     };
 
     Ok(())
 }
 
 #[instrument(skip(db))]
-pub async fn on_start(bot: Bot, msg: Message, db: Database) -> Result<()> {
-    if !db.is_active(msg.chat.id.0).await? {
-        db.add(msg.chat.id.0).await?;
-        bot.send_message(msg.chat.id, "✅ Chat was added to list")
-            .send()
-            .await?;
+async fn on_schedules(bot: Bot, msg: Message, db: Database) -> Result<()> {
+    let now = chrono::Utc::now();
+    let text = db
+        .get_schedules()
+        .await?
+        .iter()
+        .map(|entry| {
+            let next = match JobSchedule::try_from(entry) {
+                Ok(schedule) if !schedule.enabled => "disabled".to_owned(),
+                Ok(schedule) => match schedule.next_after(now) {
+                    Some(next) => format!("next at {}", next.format("%Y-%m-%d %H:%M:%S %Z")),
+                    None => "never fires".to_owned(),
+                },
+                Err(e) => format!("invalid: {}", e),
+            };
+            let last = entry
+                .last_run_at
+                .map(|last| format!("last at {}", last.format("%Y-%m-%d %H:%M:%S UTC")))
+                .unwrap_or("never ran".to_owned());
+            format!(
+                "{}: `{}` {} → {}, {}, catch-up {}",
+                entry.job, entry.cron, entry.timezone, next, last, entry.catch_up
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    let text = if text.is_empty() {
+        "There are no schedules".to_owned()
     } else {
-        let err = anyhow::anyhow!("Current chat is alread in the list");
-        let span = tracing::info_span!("Send message");
-        let guard = span.enter();
-        sentry::integrations::anyhow::capture_anyhow(&err);
-        bot.send_message(msg.chat.id, "⚠ Current chat is already in the list")
-            .send()
-            .await?;
-        drop(guard);
-    }
+        text
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
 
+#[instrument(skip(db))]
+async fn on_schedule(bot: Bot, msg: Message, db: Database, args: &str) -> Result<()> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let text = match parts[..] {
+        [job, state @ ("on" | "off")] => {
+            if db.set_schedule_enabled(job, state == "on").await? {
+                format!("✅ Job {} is {}", job, state)
+            } else {
+                format!("⚠ There is no schedule for {}", job)
+            }
+        }
+        [job, "catchup", policy] => match policy.parse::<CatchUp>() {
+            Ok(catch_up) if db.set_schedule_catch_up(job, catch_up).await? => {
+                format!("✅ Job {} catches up {}", job, catch_up)
+            }
+            Ok(_) => format!("⚠ There is no schedule for {}", job),
+            Err(e) => format!("⚠ {}", e),
+        },
+        [job, timezone, ref cron @ ..] if !cron.is_empty() => {
+            let cron = cron.join(" ");
+            match JobSchedule::parse(job, &cron, timezone, true) {
+                Ok(schedule) => {
+                    db.set_schedule(job, &cron, timezone).await?;
+                    let next = schedule
+                        .next_after(chrono::Utc::now())
+                        .map(|next| next.format("%Y-%m-%d %H:%M:%S %Z").to_string())
+                        .unwrap_or("never".to_owned());
+                    format!("✅ Job {} was rescheduled, next run at {}", job, next)
+                }
+                Err(e) => format!("⚠ {}", e),
+            }
+        }
+        _ => "Usage: /schedule <job> <timezone> <cron>, /schedule <job> on|off \
+            or /schedule <job> catchup skip|once|all"
+            .to_owned(),
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
     Ok(())
 }
 
+fn parse_toad_weight(value: &str) -> Option<f64> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|weight| weight.is_finite() && *weight > 0.)
+}
+
 #[instrument(skip(db))]
-pub async fn on_stop(bot: Bot, msg: Message, db: Database) -> Result<()> {
-    if db.is_active(msg.chat.id.0).await? {
-        db.remove(msg.chat.id.0).await?;
-        bot.send_message(msg.chat.id, "✅ Chat was removed from list")
-            .send()
-            .await?;
-    } else {
-        bot.send_message(msg.chat.id, "⚠ Current chat is not in the list")
-            .send()
-            .await?;
-    }
+async fn on_add_toad(bot: Bot, msg: Message, db: Database, args: &str) -> Result<()> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let (link, weight) = match parts[..] {
+        [link] => (link, Some(1.)),
+        [link, weight] => (link, parse_toad_weight(weight)),
+        _ => ("", None),
+    };
+    let text = match (toads::parse_video_id(link), weight) {
+        (Ok(video_id), Some(weight)) => {
+            let created_by = msg.from.as_ref().map(|user| user.id.0 as i64);
+            match db.add_toad(&video_id, weight, created_by).await? {
+                Some(id) => format!("✅ Toad #{} was added", id),
+                None => format!("⚠ Toad {} is already in the catalog", video_id),
+            }
+        }
+        (Err(e), _) if !link.is_empty() => {
+            format!("⚠ {}\nUsage: /addtoad <youtube link> [weight]", e)
+        }
+        _ => "Usage: /addtoad <youtube link> [weight], weight is a positive number".to_owned(),
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
     Ok(())
 }
 
 #[instrument(skip(db))]
-pub async fn on_status(bot: Bot, msg: Message, db: Database) -> Result<()> {
-    let active = db.is_active(msg.chat.id.0).await?;
-    let text = format!(
-        "Current chat is {}",
-        if active {
-            "in the list ✅"
-        } else {
-            "not in the list ❌"
+async fn on_toad_weight(bot: Bot, msg: Message, db: Database, args: &str) -> Result<()> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let parsed = match parts[..] {
+        [id, weight] => id
+            .trim_start_matches('#')
+            .parse::<i32>()
+            .ok()
+            .zip(parse_toad_weight(weight)),
+        _ => None,
+    };
+    let text = match parsed {
+        Some((id, weight)) if db.set_toad_weight(id, weight).await? => {
+            format!("✅ Weight of toad #{} is {} now", id, weight)
         }
-    );
+        Some((id, _)) => format!("⚠ There is no toad #{}", id),
+        None => "Usage: /toadweight <id> <weight>, weight is a positive number".to_owned(),
+    };
     bot.send_message(msg.chat.id, text).send().await?;
     Ok(())
 }
 
 #[instrument(skip(db))]
-pub async fn on_crypto_start(bot: Bot, msg: Message, db: Database) -> Result<()> {
-    if !db.is_active_crypto(msg.chat.id.0).await? {
-        db.add_crypto(msg.chat.id.0).await?;
-        bot.send_message(msg.chat.id, "✅ Chat was added to crypto list")
-            .send()
-            .await?;
+async fn on_remove_toad(bot: Bot, msg: Message, db: Database, args: &str) -> Result<()> {
+    let text = match args.trim().trim_start_matches('#').parse::<i32>() {
+        Ok(id) if db.remove_toad(id).await? => format!("✅ Toad #{} was removed", id),
+        Ok(id) => format!("⚠ There is no toad #{}", id),
+        Err(_) => "Usage: /rmtoad <id>, ids are listed by /toads".to_owned(),
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
+
+#[instrument(skip(db))]
+async fn on_toads(bot: Bot, msg: Message, db: Database) -> Result<()> {
+    let toads = db.get_toads().await?;
+    let text = if toads.is_empty() {
+        "Toad catalog is empty, add one with /addtoad".to_owned()
     } else {
-        bot.send_message(msg.chat.id, "⚠ Current chat is already in the crypto list")
-            .send()
-            .await?;
-    }
+        toads
+            .iter()
+            .map(|toad| format!("#{} {} (weight {})", toad.id, toad.url(), toad.weight))
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
 
+#[instrument(skip(db))]
+pub async fn on_subscribe(bot: Bot, msg: Message, db: Database, topic: Topic) -> Result<()> {
+    let created_by = msg.from.as_ref().map(|user| user.id.0 as i64);
+    let text = if db.subscribe(msg.chat.id.0, topic, created_by).await? {
+        format!("✅ Chat was added to {}", topic.list_name())
+    } else {
+        format!("⚠ Current chat is already in the {}", topic.list_name())
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
     Ok(())
 }
 
 #[instrument(skip(db))]
-pub async fn on_crypto_stop(bot: Bot, msg: Message, db: Database) -> Result<()> {
-    if db.is_active_crypto(msg.chat.id.0).await? {
-        db.remove_crypto(msg.chat.id.0).await?;
-        bot.send_message(msg.chat.id, "✅ Chat was removed from crypto list")
-            .send()
-            .await?;
+pub async fn on_unsubscribe(bot: Bot, msg: Message, db: Database, topic: Topic) -> Result<()> {
+    let text = if db.unsubscribe(msg.chat.id.0, topic).await? {
+        format!("✅ Chat was removed from {}", topic.list_name())
     } else {
-        bot.send_message(msg.chat.id, "⚠ Current chat is not in the crypto list")
-            .send()
-            .await?;
-    }
+        format!("⚠ Current chat is not in the {}", topic.list_name())
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
     Ok(())
 }
 
 #[instrument(skip(db))]
-pub async fn on_crypto_status(bot: Bot, msg: Message, db: Database) -> Result<()> {
-    let active = db.is_active_crypto(msg.chat.id.0).await?;
-    let text = format!(
-        "Current crypto chat is {}",
+pub async fn on_subscription_status(
+    bot: Bot,
+    msg: Message,
+    db: Database,
+    topic: Topic,
+) -> Result<()> {
+    let active = db.is_subscribed(msg.chat.id.0, topic).await?;
+    let mut text = format!(
+        "Current {}chat is {}",
+        match topic {
+            Topic::Wednesday => "",
+            Topic::Crypto => "crypto ",
+        },
         if active {
             "in the list ✅"
         } else {
             "not in the list ❌"
         }
     );
+    if active && topic == Topic::Wednesday {
+        if let Some(chat) = db.get_toad_chat(msg.chat.id.0).await? {
+            text.push_str(&format!(
+                "\nToads come on Wednesday at {} {}",
+                chat.toad_time.format("%H:%M"),
+                chat.timezone
+            ));
+        }
+    }
     bot.send_message(msg.chat.id, text).send().await?;
     Ok(())
 }
 
-#[instrument]
-pub async fn on_rates(bot: Bot, msg: Message) -> Result<()> {
-    let chat = msg.chat.id;
+#[instrument(skip(db))]
+async fn on_timezone(bot: Bot, msg: Message, db: Database, args: &str) -> Result<()> {
+    let args = args.trim();
+    let text = if args.is_empty() {
+        let timezone = db
+            .get_toad_chat(msg.chat.id.0)
+            .await?
+            .map(|chat| chat.timezone)
+            .unwrap_or(toads::DEFAULT_TIMEZONE.name().to_owned());
+        format!("Timezone of this chat is {}", timezone)
+    } else {
+        match args.parse::<chrono_tz::Tz>() {
+            Ok(timezone) => {
+                db.set_chat_timezone(msg.chat.id.0, timezone.name()).await?;
+                format!("✅ Timezone of this chat is {} now", timezone.name())
+            }
+            Err(_) => format!(
+                "⚠ Unknown timezone `{}`, use names like Europe/Berlin or UTC",
+                args
+            ),
+        }
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
 
-    let (btc, eth, bnb, sol) = match try_join!(
-        rates::get_btc_rate(),
-        rates::get_eth_rate(),
-        rates::get_bnb_rate(),
-        rates::get_sol_rate()
-    ) {
-        Ok(rates) => rates,
-        Err(e) => {
-            let e = anyhow::anyhow!(e);
-            sentry::integrations::anyhow::capture_anyhow(&e);
-            let text = format!("Failed to request currencies: {}", e);
-            tracing::error!(text);
-            bot.send_message(chat, &text).send().await.ok();
-            return Ok(());
+#[instrument(skip(db))]
+async fn on_toad_time(bot: Bot, msg: Message, db: Database, args: &str) -> Result<()> {
+    let args = args.trim();
+    let text = if args.is_empty() {
+        let toad_time = db
+            .get_toad_chat(msg.chat.id.0)
+            .await?
+            .map(|chat| chat.toad_time)
+            .unwrap_or(toads::DEFAULT_TOAD_TIME);
+        format!("Toads come at {}", toad_time.format("%H:%M"))
+    } else {
+        match chrono::NaiveTime::parse_from_str(args, "%H:%M") {
+            Ok(toad_time) => {
+                db.set_chat_toad_time(msg.chat.id.0, toad_time).await?;
+                format!("✅ Toads will come at {}", toad_time.format("%H:%M"))
+            }
+            Err(_) => format!("⚠ Could not parse time `{}`, use HH:MM", args),
         }
     };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
 
-    let text = format!(
-        "BTC = {}$\nETH = {}$\nBNB = {}$\nSOL = {}$",
-        btc, eth, bnb, sol
-    );
+#[instrument(skip(db))]
+pub async fn on_subscriptions(bot: Bot, msg: Message, db: Database) -> Result<()> {
+    let subscriptions = db.get_subscriptions(msg.chat.id.0).await?;
+    let text = if subscriptions.is_empty() {
+        "Current chat has no subscriptions".to_owned()
+    } else {
+        let mapping = db.get_mapping().await?;
+        let lines = subscriptions
+            .iter()
+            .map(|s| {
+                let since = s.created_at.format("%Y-%m-%d");
+                match s.created_by.and_then(|id| mapping.get(&id)) {
+                    Some(username) => format!("• {} since {} by {}", s.topic, since, username),
+                    None => format!("• {} since {}", s.topic, since),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!("Current chat is subscribed to:\n{}", lines)
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
+
+#[instrument(skip(coins))]
+pub async fn on_rates(bot: Bot, msg: Message, coins: CoinRegistry) -> Result<()> {
+    let chat = msg.chat.id;
+
+    let rates = join_all(coins.iter().map(rates::get_coin_rate)).await;
+
+    let text = coins
+        .iter()
+        .zip(rates)
+        .map(|(coin, rate)| match rate {
+            Ok(rate) => format!(
+                "{} = {}$ [{}]",
+                coin.ticker,
+                coin.format_price(rate.price),
+                rate.source
+            ),
+            Err(e) => {
+                tracing::error!("Failed to request {} rate: {}", coin.ticker, e);
+                format!("{} = ?", coin.ticker)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
     bot.send_message(chat, &text).send().await?;
 
     Ok(())
 }
 
-#[instrument(skip(callback))]
-async fn on_coin<Fut>(
-    bot: Bot,
-    msg: Message,
-    coin: &str,
-    callback: impl FnOnce() -> Fut,
-) -> Result<()>
-where
-    Fut: std::future::Future<Output = Result<f64>> + Send,
-{
+#[instrument(skip(coins))]
+async fn on_rate(bot: Bot, msg: Message, ticker: &str, coins: CoinRegistry) -> Result<()> {
+    let ticker = ticker.trim();
+    match coins.get(ticker) {
+        Some(coin) => on_coin(bot, msg, coin.clone()).await,
+        None => {
+            let text = if ticker.is_empty() {
+                format!(
+                    "Usage: /rate <coin>\nKnown coins: {}",
+                    coins.tickers().join(", ")
+                )
+            } else {
+                format!(
+                    "Unknown coin `{}`\nKnown coins: {}",
+                    ticker,
+                    coins.tickers().join(", ")
+                )
+            };
+            bot.send_message(msg.chat.id, text).send().await?;
+            Ok(())
+        }
+    }
+}
+
+#[instrument]
+async fn on_coin(bot: Bot, msg: Message, coin: Coin) -> Result<()> {
     let bot = WednesdayBot::new(bot, msg);
     let chat = bot.chat_id();
 
-    let rate = callback().await;
+    let rate = rates::get_coin_rate_with_24hr_change(&coin).await;
 
     let text = match rate {
-        Ok(rate) => {
-            format!("Курс {} = {}$", coin, rate)
+        Ok(rates::Rate {
+            price,
+            change: Some(change),
+            source,
+        }) => {
+            format!(
+                "Курс {} = {}$ ({:.2}%) [{}]",
+                coin.ticker,
+                coin.format_price(price),
+                change,
+                source
+            )
+        }
+        Ok(rates::Rate { price, source, .. }) => {
+            format!(
+                "Курс {} = {}$ [{}]",
+                coin.ticker,
+                coin.format_price(price),
+                source
+            )
         }
         Err(e) => {
-            tracing::error!("on_coin callback finished with error: {}", e);
+            tracing::error!("Failed to request {} rate: {}", coin.ticker, e);
             format!("Error: {}", e)
         }
     };
@@ -351,40 +651,302 @@ where
     Ok(())
 }
 
-#[instrument(skip(callback))]
-async fn on_coin_with_24hr_change<Fut>(
+/// Counts a handled command by its name without the `@bot_name` suffix.
+/// Commands are already parsed, so the name is one of the known ones.
+fn count_command(msg: &Message) {
+    let name = msg
+        .text()
+        .and_then(|text| text.split_whitespace().next())
+        .and_then(|command| command.strip_prefix('/'))
+        .and_then(|command| command.split('@').next())
+        .unwrap_or_default()
+        .to_lowercase();
+    metrics::COMMANDS.with_label_values(&[name.as_str()]).inc();
+}
+
+/// Matches `/btc` and `/btc@bot_name` shortcuts for the coins from the registry.
+fn coin_shortcut(msg: &Message, bot_name: &str, coins: &CoinRegistry) -> Option<Coin> {
+    let command = msg.text()?.split_whitespace().next()?.strip_prefix('/')?;
+    let ticker = match command.split_once('@') {
+        Some((ticker, name)) if name.eq_ignore_ascii_case(bot_name) => ticker,
+        Some(_) => return None,
+        None => command,
+    };
+    coins.get(ticker).cloned()
+}
+
+#[instrument(skip(db, coins))]
+async fn on_history(
     bot: Bot,
     msg: Message,
-    coin: &str,
-    callback: impl FnOnce() -> Fut,
-) -> Result<()>
-where
-    Fut: std::future::Future<Output = Result<(f64, f64)>> + Send,
-{
-    let chat = msg.chat.id;
+    db: Database,
+    args: &str,
+    coins: CoinRegistry,
+) -> Result<()> {
+    const USAGE: &str = "Usage: /history <coin> <period>, e.g. /history btc 7d";
 
-    let (rate, change) = callback().await?;
+    let (ticker, period) = match args.split_whitespace().collect::<Vec<&str>>()[..] {
+        [ticker, period] => (ticker, period),
+        _ => {
+            bot.send_message(msg.chat.id, USAGE).send().await?;
+            return Ok(());
+        }
+    };
 
-    let text = format!("Курс {} = {}$ ({:.2}%)", coin, rate, change);
+    let (coin, period) = match (coins.get(ticker), parse_period(period)) {
+        (Some(coin), Ok(period)) => (coin, period),
+        (None, _) => {
+            let text = format!(
+                "Unknown coin `{}`\nKnown coins: {}",
+                ticker,
+                coins.tickers().join(", ")
+            );
+            bot.send_message(msg.chat.id, text).send().await?;
+            return Ok(());
+        }
+        (_, Err(e)) => {
+            let text = format!("⚠ {}\n{}", e, USAGE);
+            bot.send_message(msg.chat.id, text).send().await?;
+            return Ok(());
+        }
+    };
 
-    bot.send_message(chat, &text).send().await?;
+    let at = chrono::Utc::now() - period;
+    let text = match db.get_price_at(&coin.ticker, at).await? {
+        Some((at, price)) => {
+            let mut text = format!(
+                "{} {} ago was {}$ ({})",
+                coin.ticker,
+                format_period(period),
+                coin.format_price(price),
+                at.format("%Y-%m-%d %H:%M UTC")
+            );
+            if let Ok(rate) = rates::get_coin_rate(coin).await {
+                text.push_str(&format!(
+                    "\nNow it is {}$ ({:+.2}%)",
+                    coin.format_price(rate.price),
+                    (rate.price / price - 1.) * 100.
+                ));
+            }
+            text
+        }
+        None => format!(
+            "There is no {} price history for {} ago",
+            coin.ticker,
+            format_period(period)
+        ),
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
     Ok(())
 }
 
-#[instrument]
-pub async fn on_dominance(bot: Bot, msg: Message, cache_pool: CachePool) -> Result<(), Error> {
-    let cache = Cache::new(cache_pool);
-    let (cached_btc, cached_eth) = try_join!(cache.get_btc_dominance(), cache.get_eth_dominance())?;
+#[instrument(skip(db, coins))]
+async fn on_chart(
+    bot: Bot,
+    msg: Message,
+    db: Database,
+    args: &str,
+    coins: CoinRegistry,
+) -> Result<()> {
+    const USAGE: &str = "Usage: /chart <coin> [24h|7d|30d] [line|candles]";
+
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (ticker, period, kind) = match args[..] {
+        [ticker] => (ticker, "24h", "candles"),
+        [ticker, period] => (ticker, period, "candles"),
+        [ticker, period, kind] => (ticker, period, kind),
+        _ => {
+            bot.send_message(msg.chat.id, USAGE).send().await?;
+            return Ok(());
+        }
+    };
+
+    let coin = match coins.get(ticker) {
+        Some(coin) => coin,
+        None => {
+            let text = format!(
+                "Unknown coin `{}`\nKnown coins: {}",
+                ticker,
+                coins.tickers().join(", ")
+            );
+            bot.send_message(msg.chat.id, text).send().await?;
+            return Ok(());
+        }
+    };
+    let period = match parse_period(period) {
+        Ok(period) if period <= chrono::Duration::days(365) => period,
+        _ => {
+            bot.send_message(msg.chat.id, USAGE).send().await?;
+            return Ok(());
+        }
+    };
+    let kind = match kind {
+        "line" => ChartKind::Line,
+        "candles" => ChartKind::Candles,
+        _ => {
+            bot.send_message(msg.chat.id, USAGE).send().await?;
+            return Ok(());
+        }
+    };
+
+    let resolution = Resolution::for_period(period);
+    let expected = (period.num_seconds() / resolution.duration().num_seconds()) as usize;
+    let mut candles = db
+        .get_candles(&coin.ticker, resolution, chrono::Utc::now() - period)
+        .await?;
+    let mut source = "history";
+
+    // Stored history may be sparse, e.g. right after the coin was added
+    if candles.len() < expected / 2 {
+        if let Some(ref symbol) = coin.binance {
+            match rates::request_klines_from_binance(symbol, resolution, expected).await {
+                Ok(klines) => {
+                    candles = klines;
+                    source = "Binance";
+                }
+                Err(e) => tracing::warn!("Failed to get {} klines: {}", coin.ticker, e),
+            }
+        }
+    }
+
+    if candles.len() < 2 {
+        let text = format!(
+            "There is not enough {} price history for {}",
+            coin.ticker,
+            format_period(period)
+        );
+        bot.send_message(msg.chat.id, text).send().await?;
+        return Ok(());
+    }
+
+    let caption = {
+        let first = &candles[0];
+        let last = &candles[candles.len() - 1];
+        let low = candles.iter().map(|c| c.low).fold(f64::MAX, f64::min);
+        let high = candles.iter().map(|c| c.high).fold(f64::MIN, f64::max);
+        format!(
+            "{} {}: {}$ → {}$ ({:+.2}%)\nLow {}$, high {}$ [{}]",
+            coin.ticker,
+            format_period(period),
+            coin.format_price(first.open),
+            coin.format_price(last.close),
+            (last.close / first.open - 1.) * 100.,
+            coin.format_price(low),
+            coin.format_price(high),
+            source
+        )
+    };
+
+    let png = tokio::task::spawn_blocking(move || chart::render(&candles, kind)).await??;
+
+    bot.send_photo(msg.chat.id, InputFile::memory(png).file_name("chart.png"))
+        .caption(caption)
+        .send()
+        .await?;
+    Ok(())
+}
+
+#[instrument(skip(db, coins))]
+async fn on_alert(
+    bot: Bot,
+    msg: Message,
+    db: Database,
+    args: &str,
+    coins: CoinRegistry,
+) -> Result<()> {
+    let request = match alerts::parse_alert(args) {
+        Ok(request) => request,
+        Err(e) => {
+            let text = format!("⚠ {}\n{}", e, alerts::ALERT_USAGE);
+            bot.send_message(msg.chat.id, text).send().await?;
+            return Ok(());
+        }
+    };
+
+    let coin = match coins.get(&request.coin) {
+        Some(coin) => coin,
+        None => {
+            let text = format!(
+                "⚠ Unknown coin `{}`\nKnown coins: {}",
+                request.coin,
+                coins.tickers().join(", ")
+            );
+            bot.send_message(msg.chat.id, text).send().await?;
+            return Ok(());
+        }
+    };
 
-    async fn request_dominance(cache: &Cache) -> Result<(f64, f64)> {
+    let rate = match rates::get_coin_rate(coin).await {
+        Ok(rate) => rate,
+        Err(e) => {
+            tracing::error!("Failed to request {} rate: {}", coin.ticker, e);
+            let text = format!("⚠ Could not get {} rate, try again later", coin.ticker);
+            bot.send_message(msg.chat.id, text).send().await?;
+            return Ok(());
+        }
+    };
+    let created_by = msg.from.as_ref().map(|user| user.id.0 as i64);
+    let id = db
+        .add_alert(msg.chat.id.0, created_by, &request, rate.price)
+        .await?;
+
+    let text = format!(
+        "✅ Alert #{} was added, {} is {}$ now",
+        id,
+        coin.ticker,
+        coin.format_price(rate.price)
+    );
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
+
+#[instrument(skip(db))]
+async fn on_alerts(bot: Bot, msg: Message, db: Database) -> Result<()> {
+    let alerts = db.get_alerts(msg.chat.id.0).await?;
+    let text = if alerts.is_empty() {
+        "Current chat has no alerts".to_owned()
+    } else {
+        alerts
+            .iter()
+            .map(|alert| alert.describe())
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
+
+#[instrument(skip(db))]
+async fn on_unalert(bot: Bot, msg: Message, db: Database, args: &str) -> Result<()> {
+    let text = match args.trim().trim_start_matches('#').parse::<i64>() {
+        Ok(id) if db.remove_alert(msg.chat.id.0, id).await? => {
+            format!("✅ Alert #{} was removed", id)
+        }
+        Ok(id) => format!("⚠ There is no alert #{} in the current chat", id),
+        Err(_) => "Usage: /unalert <id>".to_owned(),
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
+
+#[instrument(skip(cfg))]
+pub async fn on_dominance(bot: Bot, msg: Message, cache: Cache, cfg: &Cfg) -> Result<(), Error> {
+    let Some(ref coinmarketcap) = cfg.coinmarketcap else {
+        bot.send_message(msg.chat.id, "Dominance is not configured")
+            .send()
+            .await?;
+        return Ok(());
+    };
+    let (cached_btc, cached_eth) =
+        try_join!(cache.get_dominance("btc"), cache.get_dominance("eth"))?;
+
+    async fn request_dominance(cache: &Cache, api_key: &str) -> Result<(f64, f64)> {
         let url = "https://pro-api.coinmarketcap.com/v1/global-metrics/quotes/latest";
-        let cfg = crate::config::Cfg::new()?;
-        let api_key = cfg.coin_market_api_key.clone();
 
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
             "X-CMC_PRO_API_KEY",
-            reqwest::header::HeaderValue::from_str(&api_key)?,
+            reqwest::header::HeaderValue::from_str(api_key)?,
         );
         let client = reqwest::ClientBuilder::new()
             .default_headers(headers)
@@ -414,8 +976,8 @@ pub async fn on_dominance(bot: Bot, msg: Message, cache_pool: CachePool) -> Resu
         let data: Data = serde_json::from_value(response.data)?;
 
         try_join!(
-            cache.set_btc_dominance(data.btc_dominance),
-            cache.set_eth_dominance(data.eth_dominance)
+            cache.set_dominance("btc", data.btc_dominance),
+            cache.set_dominance("eth", data.eth_dominance)
         )?;
 
         Ok((data.btc_dominance, data.eth_dominance))
@@ -424,7 +986,7 @@ pub async fn on_dominance(bot: Bot, msg: Message, cache_pool: CachePool) -> Resu
     let (btc, eth) = if let (Some(btc), Some(eth)) = (cached_btc, cached_eth) {
         (btc, eth)
     } else {
-        request_dominance(&cache).await?
+        request_dominance(&cache, &coinmarketcap.api_key).await?
     };
 
     let text = format!("BTC dominance = {:.2}%\nETH dominance = {:.2}%", btc, eth);
@@ -442,153 +1004,460 @@ pub async fn text_handler(
     let user_id = if let Some(ref from) = msg.from {
         from.id
     } else {
-        tracing::debug!("Could not update statistics for message: {:?}", msg);
+        tracing::debug!("Message without a sender is not relayed: {:?}", msg);
         return Ok(());
     };
 
-    update_users_mapping(&bot, &msg.from, pool.clone()).await?;
-
     if msg.chat.is_private() {
-        bot.send_message(
-            ChatId(admin_user_id.0),
-            format!("`reply|{}|Type here...`", user_id.0),
-        )
-        .parse_mode(MarkdownV2)
-        .send()
-        .await?;
-        bot.forward_message(ChatId(admin_user_id.0), msg.chat.id, msg.id)
-            .await?;
+        relay_to_admin(&bot, &msg, user_id, pool, admin_user_id).await?;
     }
 
     Ok(())
 }
 
+/// Forwards a private message to everyone who handles tickets as a part of the user's ticket.
+async fn relay_to_admin(
+    bot: &Bot,
+    msg: &Message,
+    user_id: UserId,
+    pool: Pool,
+    owner: AdminUserId,
+) -> Result<()> {
+    let db = Database::new(pool).await?;
+    let (ticket_id, created) = db.open_ticket(user_id.0 as i64).await?;
+
+    let mut admins = vec![owner.0];
+    for admin in db.get_admins().await? {
+        if admin.role.can(Permission::Moderate) && !admins.contains(&admin.user_id) {
+            admins.push(admin.user_id);
+        }
+    }
+
+    let user = match msg.from {
+        Some(ref from) => match from.username {
+            Some(ref username) => format!("@{} ({})", username, user_id),
+            None => format!("{} ({})", from.full_name(), user_id),
+        },
+        None => user_id.to_string(),
+    };
+
+    for admin in admins {
+        let result: Result<()> = async {
+            if created {
+                let header = bot
+                    .send_message(
+                        ChatId(admin),
+                        format!(
+                            "🎫 Ticket #{} from {}\nReply to its messages to answer, /close {} when done",
+                            ticket_id, user, ticket_id
+                        ),
+                    )
+                    .send()
+                    .await?;
+                db.add_ticket_message(ticket_id, admin, header.id.0, msg.id.0, false)
+                    .await?;
+            }
+
+            let forwarded = bot.forward_message(ChatId(admin), msg.chat.id, msg.id).await?;
+            db.add_ticket_message(ticket_id, admin, forwarded.id.0, msg.id.0, false)
+                .await?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::error!("Failed to relay ticket #{} to {}: {}", ticket_id, admin, e);
+        }
+    }
+    Ok(())
+}
+
 pub async fn admin_text_handler(
     bot: Bot,
     msg: Message,
+    role: AdminRole,
     pool: Pool,
-    admin_user_id: AdminUserId,
+    owner: AdminUserId,
 ) -> Result<()> {
+    if let Some(reply_to) = msg.reply_to_message() {
+        let db = Database::new(pool.clone()).await?;
+        let ticket = db
+            .get_ticket_by_admin_message(msg.chat.id.0, reply_to.id.0, owner.0)
+            .await?;
+        if let Some(ticket) = ticket {
+            if !role.can(Permission::Moderate) {
+                bot.send_message(
+                    msg.chat.id,
+                    format!("⚠ The {} role can't answer tickets", role),
+                )
+                .send()
+                .await?;
+                return Ok(());
+            }
+            return on_ticket_reply(bot, msg, db, ticket).await;
+        }
+    }
+
     let text = match msg.text().or(msg.caption()) {
         Some(text) => text,
         None => {
-            bot.send_message(ChatId(admin_user_id.0), format!("No text in reply"))
+            bot.send_message(msg.chat.id, "No text in reply")
                 .send()
                 .await?;
             return Ok(());
         }
     };
 
-    if text.starts_with("reply|") {
-        let parts: Vec<&str> = text.split("|").into_iter().collect();
-        if parts.len() < 3 {
-            bot.send_message(
-                ChatId(admin_user_id.0),
-                format!("Insufficent size of parts vector: {}", parts.len()),
-            )
-            .send()
-            .await?;
+    if let Some(request) = campaigns::parse_broadcast(text) {
+        if !role.can(Permission::Broadcast) {
+            bot.send_message(msg.chat.id, format!("⚠ The {} role can't broadcast", role))
+                .send()
+                .await?;
             return Ok(());
         }
+        let db = Database::new(pool).await?;
+        on_broadcast(bot, msg.clone(), db, request).await?;
+    }
+    Ok(())
+}
 
-        let chat_id = match parts[1].parse::<i64>() {
-            Ok(chat_id) => ChatId(chat_id),
-            Err(e) => {
-                bot.send_message(
-                    ChatId(admin_user_id.0),
-                    format!("Could not parse chat_id: {}", e),
-                )
-                .send()
-                .await?;
-                return Ok(());
-            }
-        };
+#[instrument(skip(db))]
+async fn on_admins(bot: Bot, msg: Message, db: Database, owner: AdminUserId) -> Result<()> {
+    let mut lines = vec![format!("{} (config): owner", owner.0)];
+    lines.extend(db.get_admins().await?.iter().map(Admin::describe));
+    bot.send_message(msg.chat.id, lines.join("\n"))
+        .send()
+        .await?;
+    Ok(())
+}
 
-        let reply_text = escape_text(parts[2..].to_vec().join(""))?;
+/// Users are referenced by id or by the username known from the mapping.
+async fn resolve_user(db: &Database, user: &str) -> Result<Option<i64>> {
+    match user.parse::<i64>() {
+        Ok(user_id) => Ok(Some(user_id)),
+        Err(_) => db.find_user_id(user).await,
+    }
+}
 
-        match msg.photo() {
-            Some(photos) => {
-                bot.send_photo(chat_id, InputFile::file_id(photos[0].file.id.clone()))
-                    .caption(reply_text)
-                    .parse_mode(MarkdownV2)
-                    .send()
-                    .await?;
+#[instrument(skip(db))]
+async fn on_grant(
+    bot: Bot,
+    msg: Message,
+    db: Database,
+    args: &str,
+    owner: AdminUserId,
+) -> Result<()> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let text = match parts[..] {
+        [user, role] => match (resolve_user(&db, user).await?, role.parse::<AdminRole>()) {
+            (Some(user_id), _) if user_id == owner.0 => {
+                "⚠ The owner from the config always has every permission".to_owned()
             }
-            None => {
-                bot.send_message(chat_id, reply_text)
-                    .parse_mode(MarkdownV2)
-                    .send()
-                    .await?;
+            (Some(user_id), Ok(role)) => {
+                let granted_by = msg.from.as_ref().map(|user| user.id.0 as i64);
+                db.grant_admin(user_id, role, granted_by).await?;
+                format!("✅ {} is {} now", user, role)
+            }
+            (None, _) => format!(
+                "⚠ Unknown user {}, they should write to the bot first",
+                user
+            ),
+            (_, Err(e)) => format!("⚠ {}", e),
+        },
+        _ => "Usage: /grant <user id or @username> owner|moderator|broadcaster".to_owned(),
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
+
+#[instrument(skip(db))]
+async fn on_revoke(
+    bot: Bot,
+    msg: Message,
+    db: Database,
+    args: &str,
+    owner: AdminUserId,
+) -> Result<()> {
+    let user = args.trim();
+    let text = if user.is_empty() {
+        "Usage: /revoke <user id or @username>".to_owned()
+    } else {
+        match resolve_user(&db, user).await? {
+            Some(user_id) if user_id == owner.0 => {
+                "⚠ The owner from the config can't be revoked".to_owned()
             }
+            Some(user_id) if db.revoke_admin(user_id).await? => {
+                format!("✅ {} is not an admin anymore", user)
+            }
+            _ => format!("⚠ {} is not an admin", user),
         }
-    } else if text.starts_with("broadcast|") {
-        let parts: Vec<&str> = text.split("|").into_iter().collect();
-        if parts.len() < 2 {
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
+
+/// Any kind of message is copied, so documents, voices and stickers reach the user too.
+#[instrument(skip(db))]
+async fn on_ticket_reply(bot: Bot, msg: Message, db: Database, ticket: Ticket) -> Result<()> {
+    if ticket.status == TicketStatus::Closed {
+        bot.send_message(
+            msg.chat.id,
+            format!("⚠ Ticket #{} is closed, the reply wasn't sent", ticket.id),
+        )
+        .send()
+        .await?;
+        return Ok(());
+    }
+
+    match bot
+        .copy_message(ChatId(ticket.user_id), msg.chat.id, msg.id)
+        .send()
+        .await
+    {
+        Ok(copied) => {
+            db.add_ticket_message(ticket.id, msg.chat.id.0, msg.id.0, copied.0, true)
+                .await?;
+        }
+        Err(e) => {
             bot.send_message(
-                ChatId(admin_user_id.0),
-                format!("Insufficent size of parts vector: {}", parts.len()),
+                msg.chat.id,
+                format!("⚠ Could not answer ticket #{}: {}", ticket.id, e),
             )
             .send()
             .await?;
+        }
+    }
+    Ok(())
+}
+
+#[instrument(skip(db))]
+async fn on_tickets(bot: Bot, msg: Message, db: Database) -> Result<()> {
+    let tickets = db.get_open_tickets().await?;
+    let text = if tickets.is_empty() {
+        "There are no open tickets 🎉".to_owned()
+    } else {
+        tickets
+            .iter()
+            .map(Ticket::describe)
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
+
+#[instrument(skip(db))]
+async fn on_close_ticket(bot: Bot, msg: Message, db: Database, args: &str) -> Result<()> {
+    let text = match args.trim().trim_start_matches('#').parse::<i64>() {
+        Ok(id) if db.close_ticket(id).await? => format!("✅ Ticket #{} was closed", id),
+        Ok(id) => format!("⚠ There is no open ticket #{}", id),
+        Err(_) => "Usage: /close <id>, open tickets are listed by /tickets".to_owned(),
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
+
+/// Saves the broadcast as a draft campaign and asks the admin to confirm it.
+#[instrument(skip(db))]
+async fn on_broadcast(
+    bot: Bot,
+    msg: Message,
+    db: Database,
+    request: campaigns::BroadcastRequest,
+) -> Result<()> {
+    let text = escape_text(request.text)?;
+    let photo = msg.photo().map(|photos| photos[0].file.id.0.clone());
+    let created_by = msg.from.as_ref().map(|user| user.id.0 as i64);
+    let id = db
+        .create_campaign(request.target, &text, photo.as_deref(), created_by)
+        .await?;
+    let chats = db.get_target_chats(request.target).await?;
+
+    // Preview is sent exactly as the chats will see it
+    match photo {
+        Some(file_id) => {
+            bot.send_photo(msg.chat.id, InputFile::file_id(FileId(file_id)))
+                .caption(&text)
+                .parse_mode(MarkdownV2)
+                .send()
+                .await?;
+        }
+        None => {
+            bot.send_message(msg.chat.id, &text)
+                .parse_mode(MarkdownV2)
+                .send()
+                .await?;
+        }
+    }
+
+    let keyboard = InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback("✅ Send", format!("campaign:send:{}", id)),
+        InlineKeyboardButton::callback("❌ Cancel", format!("campaign:cancel:{}", id)),
+    ]]);
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Campaign #{} to {} chats ({}). Send it?",
+            id,
+            chats.len(),
+            request.target
+        ),
+    )
+    .reply_markup(keyboard)
+    .send()
+    .await?;
+    Ok(())
+}
+
+/// Buttons of a campaign pressed by someone who can't broadcast, the spinner is stopped anyway.
+pub async fn campaign_denied_endpoint(bot: Bot, query: CallbackQuery) -> Result<()> {
+    bot.answer_callback_query(query.id)
+        .text("⚠ You are not allowed to send campaigns")
+        .send()
+        .await?;
+    Ok(())
+}
+
+pub async fn campaign_callback_endpoint(
+    bot: Bot,
+    query: CallbackQuery,
+    pool: Pool,
+    outbox: Outbox,
+) -> Result<()> {
+    let (action, id) = match query
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix("campaign:"))
+        .and_then(|data| data.split_once(':'))
+        .and_then(|(action, id)| Some((action, id.parse::<i64>().ok()?)))
+    {
+        Some(parsed) => parsed,
+        None => {
+            bot.answer_callback_query(query.id).send().await?;
             return Ok(());
         }
+    };
 
-        let broadcast_text = escape_text(parts[1..].to_vec().join(""))?;
+    let db = Database::new(pool.clone()).await?;
+    let status = match action {
+        "send" => CampaignStatus::Sending,
+        "cancel" => CampaignStatus::Cancelled,
+        _ => {
+            tracing::warn!(
+                "Unknown campaign action {:?} from {}",
+                action,
+                query.from.id
+            );
+            bot.answer_callback_query(query.id).send().await?;
+            return Ok(());
+        }
+    };
+    let text = if db.leave_campaign_draft(id, status).await? {
+        match status {
+            CampaignStatus::Sending => format!("📤 Campaign #{} is being sent", id),
+            _ => format!("🗑 Campaign #{} was cancelled", id),
+        }
+    } else {
+        format!("⚠ Campaign #{} was already handled", id)
+    };
+    bot.answer_callback_query(query.id.clone())
+        .text(&text)
+        .send()
+        .await?;
 
-        let db = Database::new(pool).await?;
-        let chats = db.get_all_active_chats().await?;
-
-        for chat in chats {
-            let chat_id = ChatId(chat);
-            match msg.photo() {
-                Some(photos) => {
-                    bot.send_photo(chat_id, InputFile::file_id(photos[0].file.id.clone()))
-                        .caption(&broadcast_text)
-                        .parse_mode(MarkdownV2)
-                        .send()
-                        .await
-                        .map_err(|e| {
-                            tracing::error!(
-                                "Failed to send broacast message to {}. Cause: {}",
-                                chat_id,
-                                e
-                            );
-                        })
-                        .ok();
-                }
-                None => {
-                    bot.send_message(chat_id, &broadcast_text)
-                        .parse_mode(MarkdownV2)
+    if let Some(ref message) = query.message {
+        bot.edit_message_text(message.chat().id, message.id(), &text)
+            .send()
+            .await?;
+    }
+
+    if status == CampaignStatus::Sending {
+        if let Some(campaign) = db.get_campaign(id).await? {
+            let chat_id = ChatId(query.from.id.0 as i64);
+            // Sending takes a while, the dispatcher shouldn't wait for it, the shutdown does
+            outbox.clone().spawn(async move {
+                if let Err(e) = run_campaign(bot.clone(), db, outbox, campaign, chat_id).await {
+                    tracing::error!("Campaign #{} failed: {}", id, e);
+                    bot.send_message(chat_id, format!("⚠ Campaign #{} failed: {}", id, e))
                         .send()
                         .await
-                        .map_err(|e| {
-                            tracing::error!(
-                                "Failed to send broacast message to {}. Cause: {}",
-                                chat_id,
-                                e
-                            );
-                        })
                         .ok();
                 }
-            }
+            });
         }
     }
     Ok(())
 }
 
-#[instrument]
-pub async fn messages_handler(bot: Bot, msg: Message, message: Message, pool: Pool) -> Result<()> {
-    async fn impl_fn(bot: Bot, msg: Message, _message: Message, pool: Pool) -> Result<()> {
-        update_users_mapping(&bot, &msg.from, pool.clone()).await?;
+async fn run_campaign(
+    bot: Bot,
+    db: Database,
+    outbox: Outbox,
+    campaign: Campaign,
+    report_to: ChatId,
+) -> Result<()> {
+    let chats = db.get_target_chats(campaign.target).await?;
+    tracing::info!("Sending campaign #{} to {} chats", campaign.id, chats.len());
+
+    let deliveries = chats
+        .into_iter()
+        .map(|chat| {
+            let delivery = match campaign.photo_file_id {
+                Some(ref file_id) => {
+                    Delivery::photo(chat, FileId(file_id.clone()), Some(campaign.text.clone()))
+                }
+                None => Delivery::text(chat, &campaign.text),
+            };
+            let delivery = delivery.parse_mode(MarkdownV2).kind("broadcast");
+            match campaign.target.topic() {
+                Some(topic) => delivery.topic(topic),
+                None => delivery.all_topics(),
+            }
+        })
+        .collect();
+    let outcomes = outbox.deliver_all(deliveries).await?;
+    let report = Report::from_outcomes(&outcomes);
+    db.finish_campaign(campaign.id, &report).await?;
+
+    bot.send_message(
+        report_to,
+        format!(
+            "📬 Campaign #{} to {} is done: sent {}, failed {}, blocked {}",
+            campaign.id, campaign.target, report.sent, report.failed, report.blocked
+        ),
+    )
+    .send()
+    .await?;
+    Ok(())
+}
 
-        Ok(())
+#[instrument(skip(db))]
+async fn on_campaigns(bot: Bot, msg: Message, db: Database) -> Result<()> {
+    let campaigns = db.get_campaigns(10).await?;
+    let text = if campaigns.is_empty() {
+        "There are no campaigns yet, start one with broadcast|<target>|<text>".to_owned()
+    } else {
+        campaigns
+            .iter()
+            .map(Campaign::describe)
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
+    Ok(())
+}
+
+/// Records the sender and chat members before the message is handled by any branch.
+#[instrument]
+pub async fn messages_handler(bot: Bot, msg: Message, pool: Pool) {
+    async fn impl_fn(bot: &Bot, msg: &Message, pool: Pool) -> Result<()> {
+        update_users_mapping(bot, &msg.from, pool.clone()).await?;
+        track_chat_member(msg, &Database::new(pool).await?).await
     }
 
-    if let Err(e) = impl_fn(bot, msg, message, pool).await {
+    if let Err(e) = impl_fn(&bot, &msg, pool).await {
+        tracing::error!("Failed to update chat members: {}", e);
         sentry::integrations::anyhow::capture_anyhow(&e);
     }
-
-    Ok(())
 }
 
 #[instrument]
@@ -615,6 +1484,7 @@ pub async fn update_users_mapping(_bot: &Bot, user: &Option<User>, pool: Pool) -
     Ok(())
 }
 
+#[allow(dead_code)]
 #[instrument]
 pub async fn process_sticker(bot: Bot, msg: Message, sticker: &Sticker) -> Result<()> {
     const FORBIDDEN_STICKER_ID: &str = "AgADvgADzHD_Ag";
@@ -627,7 +1497,7 @@ pub async fn process_sticker(bot: Bot, msg: Message, sticker: &Sticker) -> Resul
             _ => return Ok(()),
         };
 
-        let username = from.username.as_ref().or(Some(&from.first_name)).unwrap();
+        let username = from.username.as_ref().unwrap_or(&from.first_name);
         let text = format!("@{}, хватит душить котов!", username);
 
         bot.send_message(msg.chat.id, text).send().await?;
@@ -642,22 +1512,84 @@ async fn on_usd(bot: Bot, msg: Message) -> Result<()> {
 
     let rate = rates::get_usd_rate().await?;
 
-    let text = format!("Курс {} = {:.2}₽", "USD", rate);
+    let text = format!("Курс {} = {:.2}₽ [{}]", "USD", rate.price, rate.source);
 
     bot.send_message(chat, &text).send().await?;
     Ok(())
 }
 
-#[instrument]
-async fn on_all(bot: Bot, msg: Message) -> Result<()> {
-    let _bot = &bot;
+#[instrument(skip(db))]
+async fn on_all(bot: Bot, msg: Message, db: Database) -> Result<()> {
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, "⚠ /all works in group chats only")
+            .send()
+            .await?;
+        return Ok(());
+    }
+
+    let sender = msg.from.as_ref().map(|user| user.id.0 as i64);
+    let members: Vec<ChatMember> = db
+        .get_mentionable_members(msg.chat.id.0)
+        .await?
+        .into_iter()
+        .filter(|member| Some(member.user_id) != sender)
+        .collect();
+
+    if members.is_empty() {
+        bot.send_message(msg.chat.id, "Nobody to mention yet 🤷")
+            .send()
+            .await?;
+        return Ok(());
+    }
 
-    let _user_id = msg.from.unwrap().id;
-    let _chat_id = msg.chat.id;
+    for text in members::mention_messages(&members, members::MENTIONS_PER_MESSAGE)? {
+        bot.send_message(msg.chat.id, text)
+            .parse_mode(MarkdownV2)
+            .send()
+            .await?;
+    }
+    Ok(())
+}
+
+#[instrument(skip(db))]
+async fn on_no_all(bot: Bot, msg: Message, db: Database) -> Result<()> {
+    let user_id = match msg.from {
+        Some(ref user) if !msg.chat.is_private() => user.id.0 as i64,
+        _ => {
+            bot.send_message(msg.chat.id, "⚠ /noall works in group chats only")
+                .send()
+                .await?;
+            return Ok(());
+        }
+    };
 
+    let text = if db.toggle_mention_opt_out(msg.chat.id.0, user_id).await? {
+        "✅ You won't be mentioned by /all in this chat, send /noall again to undo"
+    } else {
+        "✅ You will be mentioned by /all in this chat again"
+    };
+    bot.send_message(msg.chat.id, text).send().await?;
     Ok(())
 }
 
+/// Remembers who writes in group chats, so `/all` knows whom to mention.
+async fn track_chat_member(msg: &Message, db: &Database) -> Result<()> {
+    if msg.chat.is_private() {
+        return Ok(());
+    }
+    if let Some(user) = msg.left_chat_member() {
+        db.remove_chat_member(msg.chat.id.0, user.id.0 as i64)
+            .await?;
+        return Ok(());
+    }
+    match msg.from {
+        Some(ref user) if !user.is_bot => {
+            db.touch_chat_member(msg.chat.id.0, user.id.0 as i64).await
+        }
+        _ => Ok(()),
+    }
+}
+
 #[derive(Clone)]
 pub struct Gauss {
     mean: f64,
@@ -710,6 +1642,18 @@ pub fn get_handler() -> Handler<'static, Result<(), anyhow::Error>, DpHandlerDes
         Ok(())
     }
 
+    /// Admins talk to the bot in private chats only.
+    async fn admin_role(msg: Message, pool: Pool, owner: AdminUserId) -> Option<AdminRole> {
+        let user = msg.from.as_ref().filter(|_| msg.chat.is_private())?;
+        match get_admin_role(pool, user.id.0 as i64, owner).await {
+            Ok(role) => role,
+            Err(e) => {
+                tracing::error!("Failed to check admin role of {}: {}", user.id, e);
+                None
+            }
+        }
+    }
+
     fn get_toxicity_level(id: UserId) -> u32 {
         let mut rng = rand::rng();
         let mut percents = rng.random_range(0..=100);
@@ -759,6 +1703,7 @@ pub fn get_handler() -> Handler<'static, Result<(), anyhow::Error>, DpHandlerDes
     }
 
     let h = Update::filter_message()
+        .inspect_async(messages_handler)
         .branch(
             // set sentry user middleware
             dptree::filter(|msg: Message| {
@@ -775,16 +1720,14 @@ pub fn get_handler() -> Handler<'static, Result<(), anyhow::Error>, DpHandlerDes
                         ..Default::default()
                     }))
                 });
-                return false;
+                false
             })
             .endpoint(dummy),
         )
         .branch(
             dptree::entry()
                 .filter_command::<AdminCommand>()
-                .filter(move |msg: Message, admin_user_id: AdminUserId| {
-                    msg.chat.id.0 == admin_user_id.0
-                })
+                .filter_map_async(admin_role)
                 .endpoint(admin_commands_endpoint),
         )
         .branch(
@@ -792,18 +1735,39 @@ pub fn get_handler() -> Handler<'static, Result<(), anyhow::Error>, DpHandlerDes
                 .filter_command::<Command>()
                 .endpoint(commands_endpoint),
         )
+        .branch(
+            dptree::filter_map(|msg: Message, bot_name: String, coins: CoinRegistry| {
+                coin_shortcut(&msg, &bot_name, &coins)
+            })
+            // Shortcuts are not parsed as commands, so they are counted here
+            .inspect(|| metrics::COMMANDS.with_label_values(&["coin"]).inc())
+            .endpoint(on_coin),
+        )
         .branch(
             dptree::entry()
-                .filter(move |msg: Message, admin_user_id: AdminUserId| {
-                    msg.chat.id.0 == admin_user_id.0 && msg.chat.is_private()
-                })
+                .filter_map_async(admin_role)
                 .endpoint(admin_text_handler),
         )
         .branch(dptree::entry().endpoint(text_handler));
 
     let i = Update::filter_inline_query().endpoint(inline_endpoint);
 
-    dptree::entry().branch(h).branch(i)
+    let c = Update::filter_callback_query()
+        .branch(
+            dptree::filter_async(
+                |query: CallbackQuery, pool: Pool, owner: AdminUserId| async move {
+                    get_admin_role(pool, query.from.id.0 as i64, owner)
+                        .await
+                        .ok()
+                        .flatten()
+                        .is_some_and(|role| role.can(Permission::Broadcast))
+                },
+            )
+            .endpoint(campaign_callback_endpoint),
+        )
+        .branch(dptree::endpoint(campaign_denied_endpoint));
+
+    dptree::entry().branch(h).branch(i).branch(c)
 }
 
 fn get_escape_regex() -> Result<regex::Regex> {
@@ -811,7 +1775,7 @@ fn get_escape_regex() -> Result<regex::Regex> {
     Ok(escape_regex)
 }
 
-fn escape_text(text: String) -> Result<String> {
+pub(crate) fn escape_text(text: String) -> Result<String> {
     let escape_regex = get_escape_regex()?;
     Ok(escape_regex.replace_all(&text, "\\$0").to_string())
 }
@@ -0,0 +1,192 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use super::CacheStore;
+
+/// Values kept in the process, the least recently used ones are evicted over `capacity`.
+///
+/// Enough for a single instance, nothing is shared between replicas or kept over restarts.
+#[derive(Debug)]
+pub struct MemoryStore {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    slot: Slot,
+    expires_at: Option<Instant>,
+    used: u64,
+}
+
+#[derive(Debug)]
+enum Slot {
+    Value(Vec<u8>),
+    List(VecDeque<Vec<u8>>),
+}
+
+impl Entries {
+    /// A live entry, marked as used.
+    fn touch(&mut self, key: &str) -> Option<&mut Entry> {
+        let now = Instant::now();
+        if self.map.get(key)?.expires_at.is_some_and(|at| at <= now) {
+            self.map.remove(key);
+            return None;
+        }
+        self.clock += 1;
+        let entry = self.map.get_mut(key)?;
+        entry.used = self.clock;
+        Some(entry)
+    }
+
+    fn insert(&mut self, key: &str, slot: Slot, expires_at: Option<Instant>) {
+        self.clock += 1;
+        let entry = Entry {
+            slot,
+            expires_at,
+            used: self.clock,
+        };
+        self.map.insert(key.to_owned(), entry);
+    }
+
+    fn evict(&mut self, capacity: usize) {
+        if self.map.len() <= capacity {
+            return;
+        }
+        let now = Instant::now();
+        self.map
+            .retain(|_, entry| entry.expires_at.is_none_or(|at| at > now));
+        while self.map.len() > capacity {
+            let Some(key) = self
+                .map
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            self.map.remove(&key);
+        }
+    }
+}
+
+impl MemoryStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        // Entries are left consistent by every method, a panic elsewhere doesn't spoil them
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn wrong_type(key: &str) -> anyhow::Error {
+    anyhow!("Value of another type is kept in {}", key)
+}
+
+#[async_trait]
+impl CacheStore for MemoryStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.entries().touch(key).map(|entry| &entry.slot) {
+            None => Ok(None),
+            Some(Slot::Value(data)) => Ok(Some(data.clone())),
+            Some(Slot::List(_)) => Err(wrong_type(key)),
+        }
+    }
+
+    async fn set(&self, key: &str, data: Vec<u8>, ttl: Option<Duration>) -> Result<()> {
+        let mut entries = self.entries();
+        entries.insert(key, Slot::Value(data), ttl.map(|ttl| Instant::now() + ttl));
+        entries.evict(self.capacity);
+        Ok(())
+    }
+
+    async fn list(&self, key: &str) -> Result<Vec<Vec<u8>>> {
+        match self.entries().touch(key).map(|entry| &entry.slot) {
+            None => Ok(vec![]),
+            Some(Slot::List(list)) => Ok(list.iter().cloned().collect()),
+            Some(Slot::Value(_)) => Err(wrong_type(key)),
+        }
+    }
+
+    async fn push(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        max_len: Option<usize>,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        let mut entries = self.entries();
+        if entries.touch(key).is_none() {
+            entries.insert(key, Slot::List(VecDeque::new()), None);
+        }
+        let entry = entries.map.get_mut(key).expect("entry is just touched");
+        let Slot::List(ref mut list) = entry.slot else {
+            return Err(wrong_type(key));
+        };
+        list.push_front(data);
+        if let Some(max_len) = max_len {
+            list.truncate(max_len);
+        }
+        // Like `PEXPIRE` after `LPUSH`, a list without `ttl` keeps its expiry
+        if let Some(ttl) = ttl {
+            entry.expires_at = Some(Instant::now() + ttl);
+        }
+        entries.evict(self.capacity);
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<&'static str> {
+        Ok("ok, in memory")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn expires_and_evicts_least_recently_used() {
+        let store = MemoryStore::new(2);
+        store.set("A", b"1".to_vec(), None).await.unwrap();
+        store
+            .set("B", b"2".to_vec(), Some(Duration::from_millis(20)))
+            .await
+            .unwrap();
+        assert_eq!(store.get("B").await.unwrap(), Some(b"2".to_vec()));
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(store.get("B").await.unwrap(), None);
+
+        for value in [b"1", b"2", b"3", b"4"] {
+            store
+                .push("L", value.to_vec(), Some(3), None)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            store.list("L").await.unwrap(),
+            vec![b"4".to_vec(), b"3".to_vec(), b"2".to_vec()]
+        );
+        assert!(store.get("L").await.is_err());
+
+        // A is used after L, so L goes first
+        store.get("A").await.unwrap();
+        store.set("C", b"3".to_vec(), None).await.unwrap();
+        assert!(store.list("L").await.unwrap().is_empty());
+        assert_eq!(store.get("A").await.unwrap(), Some(b"1".to_vec()));
+    }
+}
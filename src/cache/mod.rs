@@ -0,0 +1,262 @@
+mod memory;
+mod store;
+
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use bb8_redis::bb8;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub type CachePool = bb8::Pool<bb8_redis::RedisConnectionManager>;
+pub type CacheConnection<'a> = bb8::PooledConnection<'a, bb8_redis::RedisConnectionManager>;
+
+use self::memory::MemoryStore;
+use self::store::{CacheStore, FailoverStore, RedisStore};
+
+use crate::config::{CacheBackend, Cfg};
+
+/// How values are stored in the cache.
+pub trait Codec<V> {
+    fn encode(value: &V) -> anyhow::Result<Vec<u8>>;
+    fn decode(data: &[u8]) -> anyhow::Result<V>;
+}
+
+pub struct Cbor;
+
+impl<V: Serialize + DeserializeOwned> Codec<V> for Cbor {
+    fn encode(value: &V) -> anyhow::Result<Vec<u8>> {
+        Ok(serde_cbor::to_vec(value)?)
+    }
+
+    fn decode(data: &[u8]) -> anyhow::Result<V> {
+        Ok(serde_cbor::from_slice(data)?)
+    }
+}
+
+/// Only tests use it, cached values stay CBOR so the existing keys remain readable.
+#[cfg(test)]
+pub struct Json;
+
+#[cfg(test)]
+impl<V: Serialize + DeserializeOwned> Codec<V> for Json {
+    fn encode(value: &V) -> anyhow::Result<Vec<u8>> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode(data: &[u8]) -> anyhow::Result<V> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// Plain strings, the way Redis stores numbers.
+pub struct Text;
+
+impl<V> Codec<V> for Text
+where
+    V: Display + FromStr,
+    V::Err: Display,
+{
+    fn encode(value: &V) -> anyhow::Result<Vec<u8>> {
+        Ok(value.to_string().into_bytes())
+    }
+
+    fn decode(data: &[u8]) -> anyhow::Result<V> {
+        std::str::from_utf8(data)?
+            .parse()
+            .map_err(|e| anyhow!("{}", e))
+    }
+}
+
+/// Values of one kind under `<KEY>_<NAMESPACE>` keys, e.g. `BTC_LAST_RATE`.
+pub struct TypedCache<K: ?Sized, V, C> {
+    store: Arc<dyn CacheStore>,
+    namespace: &'static str,
+    ttl: Option<Duration>,
+    max_len: Option<usize>,
+    types: PhantomData<fn(&K, V, C)>,
+}
+
+// Derives would require the key, value and codec types to implement them too
+impl<K: ?Sized, V, C> Clone for TypedCache<K, V, C> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            namespace: self.namespace,
+            ttl: self.ttl,
+            max_len: self.max_len,
+            types: PhantomData,
+        }
+    }
+}
+
+impl<K: ?Sized, V, C> std::fmt::Debug for TypedCache<K, V, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypedCache")
+            .field("namespace", &self.namespace)
+            .field("ttl", &self.ttl)
+            .field("max_len", &self.max_len)
+            .finish()
+    }
+}
+
+impl<K, V, C> TypedCache<K, V, C>
+where
+    K: Display + ?Sized,
+    C: Codec<V>,
+{
+    pub fn new(store: Arc<dyn CacheStore>, namespace: &'static str) -> Self {
+        Self {
+            store,
+            namespace,
+            ttl: None,
+            max_len: None,
+            types: PhantomData,
+        }
+    }
+
+    /// Values and lists expire after `ttl` since the last write.
+    pub fn ttl(self, ttl: Duration) -> Self {
+        Self {
+            ttl: Some(ttl),
+            ..self
+        }
+    }
+
+    /// Lists keep `max_len` latest values.
+    pub fn max_len(self, max_len: usize) -> Self {
+        Self {
+            max_len: Some(max_len),
+            ..self
+        }
+    }
+
+    fn key(&self, key: &K) -> String {
+        format!("{}_{}", key.to_string().to_uppercase(), self.namespace)
+    }
+
+    fn decode(&self, key: &str, data: &[u8]) -> anyhow::Result<V> {
+        C::decode(data).with_context(|| format!("Malformed value in {}", key))
+    }
+
+    pub async fn get(&self, key: &K) -> anyhow::Result<Option<V>> {
+        let key = self.key(key);
+        let data = self.store.get(&key).await?;
+        data.map(|data| self.decode(&key, &data)).transpose()
+    }
+
+    pub async fn set(&self, key: &K, value: &V) -> anyhow::Result<()> {
+        self.store
+            .set(&self.key(key), C::encode(value)?, self.ttl)
+            .await
+    }
+
+    /// Latest values first.
+    pub async fn list(&self, key: &K) -> anyhow::Result<Vec<V>> {
+        let key = self.key(key);
+        let data = self.store.list(&key).await?;
+        data.iter().map(|data| self.decode(&key, data)).collect()
+    }
+
+    pub async fn push(&self, key: &K, value: &V) -> anyhow::Result<()> {
+        self.store
+            .push(&self.key(key), C::encode(value)?, self.max_len, self.ttl)
+            .await
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RateCheck {
+    pub rate: f64,
+    pub grow: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cache {
+    store: Arc<dyn CacheStore>,
+    dominance: TypedCache<str, f64, Text>,
+    last_rates: TypedCache<str, RateCheck, Cbor>,
+}
+
+impl Cache {
+    const DOMINANCE_TTL: Duration = Duration::from_secs(20 * 60);
+    const LAST_RATES_LEN: usize = 3;
+
+    /// Requests wait this long for a Redis connection before the memory is used.
+    const REDIS_TIMEOUT: Duration = Duration::from_secs(2);
+
+    pub fn new(store: Arc<dyn CacheStore>) -> Self {
+        Self {
+            dominance: TypedCache::new(store.clone(), "DOMINANCE").ttl(Self::DOMINANCE_TTL),
+            last_rates: TypedCache::new(store.clone(), "LAST_RATE").max_len(Self::LAST_RATES_LEN),
+            store,
+        }
+    }
+
+    /// Redis falls back to memory while unavailable, it is not required to start.
+    pub fn open(cfg: &Cfg) -> anyhow::Result<Self> {
+        let memory = MemoryStore::new(cfg.cache.capacity);
+        let store: Arc<dyn CacheStore> = match cfg.cache.backend {
+            CacheBackend::Memory => Arc::new(memory),
+            CacheBackend::Redis => {
+                let manager = bb8_redis::RedisConnectionManager::new(format!(
+                    "redis://{}",
+                    cfg.redis.address
+                ))?;
+                let pool = bb8::Pool::builder()
+                    .connection_timeout(Self::REDIS_TIMEOUT)
+                    .build_unchecked(manager);
+                Arc::new(FailoverStore::new(RedisStore::new(pool), memory))
+            }
+        };
+        Ok(Self::new(store))
+    }
+
+    pub async fn ping(&self) -> anyhow::Result<&'static str> {
+        self.store.ping().await
+    }
+
+    pub async fn get_dominance(&self, ticker: &str) -> anyhow::Result<Option<f64>> {
+        self.dominance.get(ticker).await
+    }
+
+    pub async fn set_dominance(&self, ticker: &str, value: f64) -> anyhow::Result<()> {
+        self.dominance.set(ticker, &value).await
+    }
+
+    pub async fn get_last_rate(&self, ticker: &str) -> anyhow::Result<Vec<RateCheck>> {
+        self.last_rates.list(ticker).await
+    }
+
+    pub async fn add_last_rate(&self, ticker: &str, value: &RateCheck) -> anyhow::Result<()> {
+        self.last_rates.push(ticker, value).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codecs_report_malformed_values() {
+        let check = RateCheck {
+            rate: 60000.5,
+            grow: true,
+        };
+        let cbor: RateCheck = Cbor::decode(&Cbor::encode(&check).unwrap()).unwrap();
+        let json: RateCheck = Json::decode(&Json::encode(&check).unwrap()).unwrap();
+        assert_eq!((cbor.rate, cbor.grow), (check.rate, check.grow));
+        assert_eq!((json.rate, json.grow), (check.rate, check.grow));
+        assert!(<Cbor as Codec<RateCheck>>::decode(b"garbage").is_err());
+        assert!(<Json as Codec<RateCheck>>::decode(b"{}").is_err());
+
+        // Numbers written by `SET key 54.2` before the typed cache
+        assert_eq!(<Text as Codec<f64>>::decode(b"54.2").unwrap(), 54.2);
+        assert!(<Text as Codec<f64>>::decode(b"").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bb8_redis::redis::{self, pipe, AsyncCommands};
+
+use super::memory::MemoryStore;
+use super::{CacheConnection, CachePool};
+
+/// Raw values and lists behind `Cache`, keys are namespaced by `TypedCache`.
+#[async_trait]
+pub trait CacheStore: Send + Sync + std::fmt::Debug {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    async fn set(&self, key: &str, data: Vec<u8>, ttl: Option<Duration>) -> Result<()>;
+    /// Latest values first.
+    async fn list(&self, key: &str) -> Result<Vec<Vec<u8>>>;
+    /// Keeps `max_len` latest values, `ttl` is renewed on every push.
+    async fn push(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        max_len: Option<usize>,
+        ttl: Option<Duration>,
+    ) -> Result<()>;
+    /// State of the store shown by `/readyz`, an error means it can't be used.
+    async fn ping(&self) -> Result<&'static str>;
+}
+
+#[derive(Debug)]
+pub struct RedisStore {
+    pool: CachePool,
+}
+
+impl RedisStore {
+    pub fn new(pool: CachePool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> Result<CacheConnection<'_>> {
+        Ok(self.pool.get().await?)
+    }
+}
+
+#[async_trait]
+impl CacheStore for RedisStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.connection().await?.get(key).await?)
+    }
+
+    async fn set(&self, key: &str, data: Vec<u8>, ttl: Option<Duration>) -> Result<()> {
+        let mut connection = self.connection().await?;
+        let _: () = match ttl {
+            Some(ttl) => {
+                connection
+                    .pset_ex(key, data, ttl.as_millis() as u64)
+                    .await?
+            }
+            None => connection.set(key, data).await?,
+        };
+        Ok(())
+    }
+
+    async fn list(&self, key: &str) -> Result<Vec<Vec<u8>>> {
+        Ok(self.connection().await?.lrange(key, 0, -1).await?)
+    }
+
+    async fn push(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        max_len: Option<usize>,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        let mut pipe = pipe();
+        pipe.atomic().lpush(key, data).ignore();
+        if let Some(max_len) = max_len {
+            pipe.ltrim(key, 0, max_len as isize - 1).ignore();
+        }
+        if let Some(ttl) = ttl {
+            pipe.pexpire(key, ttl.as_millis() as i64).ignore();
+        }
+        let _: () = pipe.query_async(&mut *self.connection().await?).await?;
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<&'static str> {
+        let _: String = redis::cmd("PING")
+            .query_async(&mut *self.connection().await?)
+            .await?;
+        Ok("ok")
+    }
+}
+
+/// Redis, or values kept in memory while it is unavailable.
+///
+/// After a failure Redis is left alone for `RETRY_AFTER`, so requests don't wait
+/// for connection timeouts one after another.
+#[derive(Debug)]
+pub struct FailoverStore {
+    redis: RedisStore,
+    memory: MemoryStore,
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl FailoverStore {
+    const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+    pub fn new(redis: RedisStore, memory: MemoryStore) -> Self {
+        Self {
+            redis,
+            memory,
+            down_until: Mutex::new(None),
+        }
+    }
+
+    fn down_until(&self) -> std::sync::MutexGuard<'_, Option<Instant>> {
+        self.down_until.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn either<T>(
+        &self,
+        redis: impl Future<Output = Result<T>>,
+        memory: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        if self
+            .down_until()
+            .is_some_and(|until| Instant::now() < until)
+        {
+            return memory.await;
+        }
+        match redis.await {
+            Ok(value) => {
+                self.redis_up();
+                Ok(value)
+            }
+            Err(e) => {
+                self.redis_down(&e);
+                memory.await
+            }
+        }
+    }
+
+    fn redis_up(&self) {
+        if self.down_until().take().is_some() {
+            tracing::info!("Redis is back, cached values are stored there again");
+        }
+    }
+
+    fn redis_down(&self, error: &anyhow::Error) {
+        let mut down_until = self.down_until();
+        if down_until.is_none() {
+            tracing::warn!("Redis is unavailable, values are kept in memory: {}", error);
+        }
+        *down_until = Some(Instant::now() + Self::RETRY_AFTER);
+    }
+}
+
+#[async_trait]
+impl CacheStore for FailoverStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.either(self.redis.get(key), self.memory.get(key)).await
+    }
+
+    async fn set(&self, key: &str, data: Vec<u8>, ttl: Option<Duration>) -> Result<()> {
+        self.either(
+            self.redis.set(key, data.clone(), ttl),
+            self.memory.set(key, data, ttl),
+        )
+        .await
+    }
+
+    async fn list(&self, key: &str) -> Result<Vec<Vec<u8>>> {
+        self.either(self.redis.list(key), self.memory.list(key))
+            .await
+    }
+
+    async fn push(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        max_len: Option<usize>,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        self.either(
+            self.redis.push(key, data.clone(), max_len, ttl),
+            self.memory.push(key, data, max_len, ttl),
+        )
+        .await
+    }
+
+    async fn ping(&self) -> Result<&'static str> {
+        match self.redis.ping().await {
+            Ok(state) => Ok(state),
+            Err(e) => {
+                tracing::warn!("Redis is unavailable: {}", e);
+                Ok("redis is unavailable, in memory")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn falls_back_to_memory_without_redis() {
+        // Nothing listens on the discard port
+        let manager = bb8_redis::RedisConnectionManager::new("redis://127.0.0.1:9").unwrap();
+        let pool = bb8_redis::bb8::Pool::builder()
+            .connection_timeout(Duration::from_millis(200))
+            .build_unchecked(manager);
+        let store = FailoverStore::new(RedisStore::new(pool), MemoryStore::new(8));
+
+        store.set("KEY", b"value".to_vec(), None).await.unwrap();
+        assert_eq!(store.get("KEY").await.unwrap(), Some(b"value".to_vec()));
+        assert_eq!(
+            store.ping().await.unwrap(),
+            "redis is unavailable, in memory"
+        );
+    }
+}
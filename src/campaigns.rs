@@ -0,0 +1,187 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+
+use crate::database::Topic;
+use crate::delivery::Outcome;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "campaign_target", rename_all = "lowercase")]
+pub enum CampaignTarget {
+    Wednesday,
+    Crypto,
+    All,
+}
+
+impl CampaignTarget {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CampaignTarget::Wednesday => "wednesday",
+            CampaignTarget::Crypto => "crypto",
+            CampaignTarget::All => "all",
+        }
+    }
+
+    /// Topic to unsubscribe unreachable chats from, `None` for `all`, which takes every topic.
+    pub fn topic(&self) -> Option<Topic> {
+        match self {
+            CampaignTarget::Wednesday => Some(Topic::Wednesday),
+            CampaignTarget::Crypto => Some(Topic::Crypto),
+            CampaignTarget::All => None,
+        }
+    }
+}
+
+impl fmt::Display for CampaignTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CampaignTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "wednesday" => Ok(CampaignTarget::Wednesday),
+            "crypto" => Ok(CampaignTarget::Crypto),
+            "all" => Ok(CampaignTarget::All),
+            _ => Err(anyhow!("Unknown target `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "campaign_status", rename_all = "lowercase")]
+pub enum CampaignStatus {
+    Draft,
+    Sending,
+    Done,
+    Cancelled,
+}
+
+/// Row of the `campaigns` table, a broadcast from the admin.
+#[derive(Debug, Clone)]
+pub struct Campaign {
+    pub id: i64,
+    pub target: CampaignTarget,
+    pub text: String,
+    pub photo_file_id: Option<String>,
+    pub status: CampaignStatus,
+    pub created_at: DateTime<Utc>,
+    pub sent: i32,
+    pub failed: i32,
+    pub blocked: i32,
+}
+
+impl Campaign {
+    pub fn describe(&self) -> String {
+        let status = match self.status {
+            CampaignStatus::Draft => "draft".to_owned(),
+            CampaignStatus::Sending => "sending".to_owned(),
+            CampaignStatus::Cancelled => "cancelled".to_owned(),
+            CampaignStatus::Done => format!(
+                "sent {}, failed {}, blocked {}",
+                self.sent, self.failed, self.blocked
+            ),
+        };
+        format!(
+            "#{} {} to {}: {}",
+            self.id,
+            self.created_at.format("%Y-%m-%d %H:%M"),
+            self.target,
+            status
+        )
+    }
+}
+
+/// Parsed `broadcast|` message: `broadcast|<target>|text`, the target defaults to wednesday.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BroadcastRequest {
+    pub target: CampaignTarget,
+    pub text: String,
+}
+
+pub fn parse_broadcast(text: &str) -> Option<BroadcastRequest> {
+    let rest = text.strip_prefix("broadcast|")?;
+    let request = match rest.split_once('|') {
+        Some((target, text)) => match target.parse() {
+            Ok(target) => BroadcastRequest {
+                target,
+                text: text.to_owned(),
+            },
+            Err(_) => BroadcastRequest {
+                target: CampaignTarget::Wednesday,
+                text: rest.to_owned(),
+            },
+        },
+        None => BroadcastRequest {
+            target: CampaignTarget::Wednesday,
+            text: rest.to_owned(),
+        },
+    };
+    Some(request)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Report {
+    pub sent: i32,
+    pub failed: i32,
+    pub blocked: i32,
+}
+
+impl Report {
+    pub fn from_outcomes(outcomes: &[Outcome]) -> Self {
+        let mut report = Report::default();
+        for outcome in outcomes {
+            match outcome {
+                Outcome::Sent | Outcome::Migrated(_) => report.sent += 1,
+                Outcome::Blocked(_) => report.blocked += 1,
+                Outcome::Failed(_) => report.failed += 1,
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_broadcast_targets() {
+        let request = parse_broadcast("broadcast|crypto|To the moon").unwrap();
+        assert_eq!(request.target, CampaignTarget::Crypto);
+        assert_eq!(request.text, "To the moon");
+
+        let request = parse_broadcast("broadcast|It is Wednesday").unwrap();
+        assert_eq!(request.target, CampaignTarget::Wednesday);
+        assert_eq!(request.text, "It is Wednesday");
+
+        let request = parse_broadcast("broadcast|a|b").unwrap();
+        assert_eq!(request.target, CampaignTarget::Wednesday);
+        assert_eq!(request.text, "a|b");
+
+        assert!(parse_broadcast("reply|1|hi").is_none());
+    }
+
+    #[test]
+    fn report_counts_outcomes() {
+        let report = Report::from_outcomes(&[
+            Outcome::Sent,
+            Outcome::Migrated(-100),
+            Outcome::Blocked("blocked".to_owned()),
+            Outcome::Failed("timeout".to_owned()),
+        ]);
+        assert_eq!(
+            report,
+            Report {
+                sent: 2,
+                failed: 1,
+                blocked: 1
+            }
+        );
+    }
+}
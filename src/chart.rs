@@ -0,0 +1,161 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use plotters::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "candle_resolution", rename_all = "lowercase")]
+pub enum Resolution {
+    Hour,
+    Day,
+}
+
+impl Resolution {
+    /// Keeps the amount of candles on a chart readable.
+    pub fn for_period(period: Duration) -> Self {
+        if period <= Duration::days(7) {
+            Resolution::Hour
+        } else {
+            Resolution::Day
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            Resolution::Hour => Duration::hours(1),
+            Resolution::Day => Duration::days(1),
+        }
+    }
+
+    pub fn binance_interval(&self) -> &'static str {
+        match self {
+            Resolution::Hour => "1h",
+            Resolution::Day => "1d",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Candle {
+    pub bucket: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartKind {
+    Line,
+    Candles,
+}
+
+const WIDTH: u32 = 1024;
+const HEIGHT: u32 = 512;
+const GRID_LINES: usize = 5;
+
+/// Renders candles to a PNG image. There are no labels on the chart since
+/// no fonts are shipped with the bot, the numbers go to the caption instead.
+pub fn render(candles: &[Candle], kind: ChartKind) -> Result<Vec<u8>> {
+    if candles.len() < 2 {
+        return Err(anyhow!("Not enough data to draw a chart"));
+    }
+
+    let (low, high) = candles
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(low, high), candle| {
+            (low.min(candle.low), high.max(candle.high))
+        });
+    let margin = ((high - low) * 0.05).max(high.abs() * 1e-6);
+    let (low, high) = (low - margin, high + margin);
+    let count = candles.len() as f64;
+
+    // Candles are placed by time, so the gaps in history stay visible
+    let first = candles[0].bucket;
+    let x = |candle: &Candle| (candle.bucket - first).num_seconds() as f64;
+    let last = x(&candles[candles.len() - 1]);
+    let half_step = (last / (count - 1.) / 2.).max(1.);
+    let (left, right) = (-half_step, last + half_step);
+
+    let mut buffer = vec![0u8; (WIDTH * HEIGHT * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&WHITE)?;
+
+        let mut chart = ChartBuilder::on(&root)
+            .margin(20)
+            .build_cartesian_2d(left..right, low..high)?;
+
+        let grid = RGBColor(225, 225, 225);
+        for i in 0..=GRID_LINES {
+            let y = low + (high - low) * i as f64 / GRID_LINES as f64;
+            chart.draw_series(LineSeries::new(
+                [(left, y), (right, y)],
+                grid.stroke_width(1),
+            ))?;
+        }
+
+        match kind {
+            ChartKind::Line => {
+                chart.draw_series(LineSeries::new(
+                    candles.iter().map(|candle| (x(candle), candle.close)),
+                    BLUE.stroke_width(2),
+                ))?;
+            }
+            ChartKind::Candles => {
+                let width = ((WIDTH - 40) as f64 / count * 0.7).max(1.) as u32;
+                chart.draw_series(candles.iter().map(|candle| {
+                    CandleStick::new(
+                        x(candle),
+                        candle.open,
+                        candle.high,
+                        candle.low,
+                        candle.close,
+                        RGBColor(22, 163, 74).filled(),
+                        RGBColor(220, 38, 38).filled(),
+                        width,
+                    )
+                }))?;
+            }
+        }
+
+        root.present()?;
+    }
+
+    let mut png = vec![];
+    let mut encoder = png::Encoder::new(&mut png, WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&buffer)?;
+    writer.finish()?;
+
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_png() {
+        let now = Utc::now();
+        let candles: Vec<Candle> = (0..24)
+            .map(|i| {
+                let price = 100. + (i as f64).sin() * 10.;
+                Candle {
+                    bucket: now + Duration::hours(i),
+                    open: price,
+                    high: price + 3.,
+                    low: price - 3.,
+                    close: price + 1.,
+                }
+            })
+            .collect();
+
+        for kind in [ChartKind::Line, ChartKind::Candles] {
+            let png = render(&candles, kind).unwrap();
+            assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        }
+        assert!(render(&candles[..1], ChartKind::Line).is_err());
+    }
+}
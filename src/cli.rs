@@ -0,0 +1,101 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+
+use crate::config::Cfg;
+use crate::database::{Database, Pool};
+use crate::delivery::Outbox;
+use crate::dump::Dump;
+use crate::scheduler::Scheduler;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "It is Wednesday, my dudes")]
+pub struct Cli {
+    /// Validate the config and exit.
+    #[arg(long, global = true)]
+    pub check_config: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Run the bot, the default.
+    Run,
+    /// Apply pending database migrations.
+    Migrate,
+    /// Send the next toad of the rotation to a chat right now.
+    SendToad {
+        #[arg(allow_negative_numbers = true)]
+        chat_id: i64,
+    },
+    /// Dump chats, their subscriptions and the users mapping as JSON.
+    Export {
+        /// Written to stdout when omitted.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Merge chats, their subscriptions and the users mapping from a JSON dump.
+    Import { input: PathBuf },
+}
+
+/// Ops commands, they use the same database as the bot and exit when done.
+pub async fn run(command: Command, cfg: &Cfg) -> Result<()> {
+    let pool = sqlx::PgPool::connect(&cfg.db).await?;
+
+    match command {
+        Command::Run => unreachable!("the bot is run by main"),
+        Command::Migrate => {
+            Database::init(pool).await?;
+            eprintln!("Migrations are applied");
+        }
+        Command::SendToad { chat_id } => send_toad(cfg, pool, chat_id).await?,
+        Command::Export { output } => {
+            let dump = Database::new(pool).await?.export_dump().await?;
+            let json = serde_json::to_string_pretty(&dump)?;
+            match output {
+                Some(path) => tokio::fs::write(&path, json).await?,
+                None => writeln!(std::io::stdout().lock(), "{}", json)?,
+            }
+            eprintln!(
+                "Exported {} chats and {} users",
+                dump.chats.len(),
+                dump.mapping.len()
+            );
+        }
+        Command::Import { input } => {
+            let dump: Dump = serde_json::from_slice(&tokio::fs::read(&input).await?)?;
+            Database::new(pool).await?.import_dump(&dump).await?;
+            eprintln!(
+                "Imported {} chats and {} users",
+                dump.chats.len(),
+                dump.mapping.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn send_toad(cfg: &Cfg, pool: Pool, chat_id: i64) -> Result<()> {
+    let db = Database::new(pool.clone()).await?;
+    let chat = db
+        .get_toad_chat(chat_id)
+        .await?
+        .ok_or(anyhow!("Chat {} is unknown", chat_id))?;
+
+    let outbox = Outbox::new(teloxide::Bot::new(cfg.token.clone()), pool.clone());
+    let outcomes = Scheduler::send_toads_to(&outbox, pool, vec![chat]).await?;
+    match outcomes.first() {
+        None => {
+            eprintln!("Chat {} got the toad this week already", chat_id);
+            Ok(())
+        }
+        Some((_, outcome)) if outcome.is_sent() => {
+            eprintln!("Toad is sent to {}", chat_id);
+            Ok(())
+        }
+        Some((_, outcome)) => Err(anyhow!("Toad was not sent to {}: {:?}", chat_id, outcome)),
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::rates::{Binance, CoinGecko, CurrencyApi, RateSource};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Coin {
+    pub ticker: String,
+    #[serde(default)]
+    pub binance: Option<String>,
+    #[serde(default)]
+    pub coingecko: Option<String>,
+    #[serde(default)]
+    pub currency_api: Option<String>,
+    #[serde(default = "Coin::default_precision")]
+    pub precision: usize,
+    /// Price step for the scheduled rate check, the coin isn't checked when it is not set.
+    #[serde(default)]
+    pub alert_step: Option<f64>,
+}
+
+impl Coin {
+    fn default_precision() -> usize {
+        2
+    }
+
+    fn new(
+        ticker: &str,
+        binance: Option<&str>,
+        coingecko: Option<&str>,
+        currency_api: Option<&str>,
+        precision: usize,
+        alert_step: Option<f64>,
+    ) -> Self {
+        Self {
+            ticker: ticker.to_owned(),
+            binance: binance.map(ToOwned::to_owned),
+            coingecko: coingecko.map(ToOwned::to_owned),
+            currency_api: currency_api.map(ToOwned::to_owned),
+            precision,
+            alert_step,
+        }
+    }
+
+    /// Ordered list of sources for the coin, the first one is preferred.
+    pub fn sources(&self) -> Vec<Box<dyn RateSource>> {
+        let mut sources: Vec<Box<dyn RateSource>> = vec![];
+        if let Some(ref symbol) = self.binance {
+            sources.push(Box::new(Binance::new(symbol)));
+        }
+        if let Some(ref id) = self.coingecko {
+            sources.push(Box::new(CoinGecko::new(id)));
+        }
+        if let Some(ref currency) = self.currency_api {
+            sources.push(Box::new(CurrencyApi::new(currency, "usd")));
+        }
+        sources
+    }
+
+    pub fn format_price(&self, price: f64) -> String {
+        format!("{:.*}", self.precision, price)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "Vec<Coin>")]
+pub struct CoinRegistry {
+    coins: Arc<Vec<Coin>>,
+}
+
+impl CoinRegistry {
+    pub fn new(coins: Vec<Coin>) -> Self {
+        Self {
+            coins: Arc::new(coins),
+        }
+    }
+
+    pub fn get(&self, ticker: &str) -> Option<&Coin> {
+        self.coins
+            .iter()
+            .find(|coin| coin.ticker.eq_ignore_ascii_case(ticker))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Coin> {
+        self.coins.iter()
+    }
+
+    pub fn tickers(&self) -> Vec<&str> {
+        self.coins.iter().map(|coin| coin.ticker.as_str()).collect()
+    }
+}
+
+impl From<Vec<Coin>> for CoinRegistry {
+    fn from(coins: Vec<Coin>) -> Self {
+        Self::new(coins)
+    }
+}
+
+impl Default for CoinRegistry {
+    fn default() -> Self {
+        Self::new(vec![
+            Coin::new(
+                "BTC",
+                Some("BTC"),
+                Some("bitcoin"),
+                Some("btc"),
+                2,
+                Some(1000.),
+            ),
+            Coin::new(
+                "ETH",
+                Some("ETH"),
+                Some("ethereum"),
+                Some("eth"),
+                2,
+                Some(100.),
+            ),
+            Coin::new(
+                "BNB",
+                Some("BNB"),
+                Some("binancecoin"),
+                Some("bnb"),
+                2,
+                Some(10.),
+            ),
+            Coin::new("SOL", Some("SOL"), Some("solana"), Some("sol"), 2, None),
+            Coin::new(
+                "TON",
+                Some("TON"),
+                Some("the-open-network"),
+                Some("ton"),
+                3,
+                None,
+            ),
+            Coin::new("NOT", Some("NOT"), Some("notcoin"), None, 6, Some(0.001)),
+            Coin::new("LTC", Some("LTC"), Some("litecoin"), Some("ltc"), 2, None),
+            Coin::new("ETC", Some("ETC"), Some("ethereum-classic"), None, 2, None),
+            Coin::new("ADA", Some("ADA"), Some("cardano"), Some("ada"), 4, None),
+            Coin::new("ZEE", None, Some("zeroswap"), None, 4, None),
+            Coin::new("LUNA", None, Some("terra-luna"), None, 6, None),
+        ])
+    }
+}
@@ -1,35 +1,415 @@
-use anyhow::Result;
-use config;
+use std::net::SocketAddr;
 
-pub struct Cfg {
+use anyhow::{anyhow, Result};
+use config::builder::DefaultState;
+use config::{ConfigBuilder, Environment, File, Map, Value, ValueKind};
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::coins::CoinRegistry;
+
+/// Settings are layered: `config.*`, optional `config.local.*` and `WEDNESDAY_*`
+/// environment variables, sections are separated by `__` (`WEDNESDAY_REDIS__ADDRESS`).
+/// Flat keys of earlier configs, like `cache` or `WEDNESDAY_SENTRY_URL`, are still read.
+///
+/// The config is read as `RawCfg`, where `admin_user_id` may be missing, and `validate`
+/// turns it into `Cfg`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(
+    deny_unknown_fields,
+    bound(deserialize = "A: Deserialize<'de> + Default")
+)]
+pub struct Cfg<A = AdminUserId> {
+    // Blank and missing values are left to `validate`, so they are reported together
+    #[serde(default, deserialize_with = "blank_as_empty")]
     pub bot_name: String,
+    #[serde(default, deserialize_with = "blank_as_empty")]
     pub token: String,
-    pub sentry_url: String,
-    pub coin_market_api_key: String,
+    #[serde(default, deserialize_with = "blank_as_empty")]
     pub db: String,
-    pub cache: String,
+    #[serde(default)]
+    pub admin_user_id: A,
+    /// The built-in list of coins is used when omitted.
+    #[serde(default)]
+    pub coins: CoinRegistry,
+    #[serde(default)]
+    pub redis: RedisCfg,
+    #[serde(default)]
+    pub cache: CacheCfg,
+    #[serde(default)]
+    pub http: HttpCfg,
+    #[serde(default)]
+    pub scheduler: SchedulerCfg,
+    /// Errors are not reported when omitted.
+    pub sentry: Option<SentryCfg>,
+    /// `/dominance` is disabled when omitted.
+    pub coinmarketcap: Option<CoinMarketCapCfg>,
+    /// Updates are received by a webhook instead of long polling when present.
+    pub webhook: Option<WebhookCfg>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedisCfg {
+    pub address: String,
+}
+
+impl Default for RedisCfg {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheCfg {
+    pub backend: CacheBackend,
+    /// Values kept in memory, when Redis is not used or unavailable.
+    pub capacity: usize,
+}
+
+impl Default for CacheCfg {
+    fn default() -> Self {
+        Self {
+            backend: CacheBackend::Redis,
+            capacity: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    /// Shared by replicas, falls back to memory while unavailable.
+    Redis,
+    /// A single instance without Redis.
+    Memory,
+}
+
+/// Health and metrics server.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpCfg {
+    pub addr: SocketAddr,
+}
+
+impl Default for HttpCfg {
+    fn default() -> Self {
+        Self {
+            addr: SocketAddr::from(([0, 0, 0, 0], 8080)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulerCfg {
+    /// Scheduled jobs don't run on this instance when disabled.
+    pub enabled: bool,
+    /// How often schedules are checked.
+    pub tick_seconds: u64,
+}
+
+impl Default for SchedulerCfg {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            tick_seconds: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SentryCfg {
+    pub url: String,
+    #[serde(default)]
     pub traces_sample_rate: f32,
-    pub admin_user_id: AdminUserId,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CoinMarketCapCfg {
+    pub api_key: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookCfg {
+    /// Public URL Telegram posts updates to, its path is served by the listener.
+    pub url: Url,
+    /// Local address of the listener.
+    pub addr: SocketAddr,
+    /// Sent by Telegram in `X-Telegram-Bot-Api-Secret-Token`.
+    pub secret: String,
+    /// Whether to call `setWebhook` on start, off for local testing.
+    #[serde(default = "WebhookCfg::default_register")]
+    pub register: bool,
+}
+
+impl WebhookCfg {
+    fn default_register() -> bool {
+        true
+    }
+}
+
+type RawCfg = Cfg<Option<AdminUserId>>;
+
 impl Cfg {
     pub fn new() -> Result<Self> {
-        let settings = config::Config::builder()
-            .add_source(config::File::with_name("config"))
-            .add_source(config::Environment::with_prefix("WEDNESDAY"))
-            .build()?;
-        Ok(Self {
-            bot_name: settings.get_string("bot_name")?,
-            token: settings.get_string("token")?,
-            sentry_url: settings.get_string("sentry_url")?,
-            coin_market_api_key: settings.get_string("coin_market_api_key")?,
-            db: settings.get_string("db")?,
-            cache: settings.get_string("cache")?,
-            traces_sample_rate: settings.get_float("traces_sample_rate")? as f32,
-            admin_user_id: AdminUserId(settings.get_int("admin_user_id")?),
-        })
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
+        Self::load(
+            config::Config::builder()
+                .add_source(File::with_name("config"))
+                .add_source(File::with_name("config.local").required(false))
+                .add_source(
+                    Environment::with_prefix("WEDNESDAY")
+                        .prefix_separator("_")
+                        .separator("__"),
+                ),
+        )
+    }
+
+    fn load(builder: ConfigBuilder<DefaultState>) -> Result<Self> {
+        let mut values: Map<String, Value> = builder.build()?.try_deserialize()?;
+        move_legacy_keys(&mut values);
+        let cfg: RawCfg = Value::new(None, values)
+            .try_deserialize()
+            .map_err(|e| anyhow!("Invalid config: {}", e))?;
+        cfg.validate()
+    }
+}
+
+impl RawCfg {
+    /// Checks everything at once, so all problems are reported together.
+    fn validate(self) -> Result<Cfg> {
+        let mut problems = vec![];
+
+        if self.bot_name.trim().is_empty() {
+            problems.push("bot_name is not set".to_owned());
+        }
+        if !self.token.contains(':') {
+            problems.push("token doesn't look like a bot token".to_owned());
+        }
+        if self.db.trim().is_empty() {
+            problems.push("db is not set".to_owned());
+        }
+        if self.admin_user_id.is_none() {
+            problems.push("admin_user_id is not set".to_owned());
+        }
+        if self.cache.backend == CacheBackend::Redis && self.redis.address.trim().is_empty() {
+            problems.push("redis.address is empty".to_owned());
+        }
+        if self.cache.capacity == 0 {
+            problems.push("cache.capacity must be positive".to_owned());
+        }
+        if self.scheduler.tick_seconds == 0 {
+            problems.push("scheduler.tick_seconds must be positive".to_owned());
+        }
+        if let Some(ref sentry) = self.sentry {
+            if let Err(e) = sentry.url.parse::<sentry::types::Dsn>() {
+                problems.push(format!("sentry.url is invalid: {}", e));
+            }
+            if !(0. ..=1.).contains(&sentry.traces_sample_rate) {
+                problems.push("sentry.traces_sample_rate must be within 0..1".to_owned());
+            }
+        }
+        if let Some(ref coinmarketcap) = self.coinmarketcap {
+            if coinmarketcap.api_key.trim().is_empty() {
+                problems.push("coinmarketcap.api_key is empty".to_owned());
+            }
+        }
+        if let Some(ref webhook) = self.webhook {
+            if !is_valid_secret(&webhook.secret) {
+                problems.push(
+                    "webhook.secret must be 1-256 characters of A-Z, a-z, 0-9, _ and -".to_owned(),
+                );
+            }
+            if webhook.addr == self.http.addr {
+                problems.push("webhook.addr is taken by http.addr".to_owned());
+            }
+        }
+        for (i, coin) in self.coins.iter().enumerate() {
+            if coin.sources().is_empty() {
+                problems.push(format!("coin {} has no rate sources", coin.ticker));
+            }
+            if self
+                .coins
+                .iter()
+                .take(i)
+                .any(|other| other.ticker.eq_ignore_ascii_case(&coin.ticker))
+            {
+                problems.push(format!("coin {} is listed twice", coin.ticker));
+            }
+        }
+
+        if let Some(admin_user_id) = self.admin_user_id.filter(|_| problems.is_empty()) {
+            return Ok(Cfg {
+                bot_name: self.bot_name,
+                token: self.token,
+                db: self.db,
+                admin_user_id,
+                coins: self.coins,
+                redis: self.redis,
+                cache: self.cache,
+                http: self.http,
+                scheduler: self.scheduler,
+                sentry: self.sentry,
+                coinmarketcap: self.coinmarketcap,
+                webhook: self.webhook,
+            });
+        }
+        Err(anyhow!(
+            "Invalid config:\n{}",
+            problems
+                .iter()
+                .map(|problem| format!("  - {}", problem))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ))
+    }
+}
+
+/// Flat keys of earlier configs and their places in sections. The last ones only fill
+/// a section which is already there, so a lone `traces_sample_rate` doesn't enable Sentry.
+const LEGACY_KEYS: [(&str, &str, &str, bool); 4] = [
+    ("cache", "redis", "address", true),
+    ("sentry_url", "sentry", "url", true),
+    ("coin_market_api_key", "coinmarketcap", "api_key", true),
+    ("traces_sample_rate", "sentry", "traces_sample_rate", false),
+];
+
+/// Keys in sections take precedence, blank legacy values are dropped like omitted ones.
+fn move_legacy_keys(values: &mut Map<String, Value>) {
+    for (key, section, field, creates_section) in LEGACY_KEYS {
+        let Some(value) = values.remove(key) else {
+            continue;
+        };
+        match value.kind {
+            // `cache` is a section now
+            ValueKind::Table(_) => {
+                values.insert(key.to_owned(), value);
+                continue;
+            }
+            ValueKind::Nil => continue,
+            ValueKind::String(ref s) if s.trim().is_empty() => continue,
+            _ => {}
+        }
+        if !creates_section && !values.contains_key(section) {
+            continue;
+        }
+        let section = values
+            .entry(section.to_owned())
+            .or_insert_with(|| Value::new(None, Map::<String, Value>::new()));
+        if let ValueKind::Table(ref mut table) = section.kind {
+            table.entry(field.to_owned()).or_insert(value);
+        }
+    }
+}
+
+fn blank_as_empty<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+fn is_valid_secret(secret: &str) -> bool {
+    (1..=256).contains(&secret.len())
+        && secret
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// Owner of the bot, other admins are granted roles in the `admins` table.
+#[derive(Debug, Clone, Copy, Deserialize)]
 pub struct AdminUserId(pub i64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::FileFormat;
+
+    fn load(yaml: &str) -> Result<Cfg> {
+        Cfg::load(config::Config::builder().add_source(File::from_str(yaml, FileFormat::Yaml)))
+    }
+
+    #[test]
+    fn defaults_and_problems() {
+        let cfg =
+            load("bot_name: wednesday\ntoken: '1:abc'\ndb: host=db\nadmin_user_id: 1\n").unwrap();
+        assert_eq!(cfg.admin_user_id.0, 1);
+        assert_eq!(cfg.redis.address, "127.0.0.1");
+        assert_eq!(cfg.http.addr.port(), 8080);
+        assert!(cfg.scheduler.enabled);
+        assert_eq!(cfg.cache.backend, CacheBackend::Redis);
+        assert!(cfg.sentry.is_none() && cfg.webhook.is_none());
+        assert!(cfg.coins.get("btc").is_some());
+
+        let error = load(
+            "bot_name: ''\ntoken: abc\ndb: host=db\n\
+             webhook:\n  url: https://bot.example.com/hook\n  addr: 0.0.0.0:8080\n  secret: 'a b'\n",
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(error.lines().count(), 6, "{}", error);
+        assert!(error.contains("webhook.addr is taken"));
+        assert!(error.contains("admin_user_id is not set"));
+
+        assert!(
+            load("bot_name: a\ntoken: '1:a'\ndb: b\nadmin_user_id: 1\ncaches: redis\n").is_err()
+        );
+    }
+
+    #[test]
+    fn legacy_keys() {
+        // config.yaml.example before the sections
+        let cfg = load(
+            "db: host=db\ntoken: '1:abc'\ncache: 10.0.0.2\nsentry_url:\nbot_name: wednesday\n\
+             coin_market_api_key: key\ntraces_sample_rate: 0\nadmin_user_id: -1\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.redis.address, "10.0.0.2");
+        assert_eq!(cfg.cache.backend, CacheBackend::Redis);
+        assert!(cfg.sentry.is_none());
+        assert_eq!(cfg.coinmarketcap.unwrap().api_key, "key");
+
+        let cfg = load(
+            "bot_name: a\ntoken: '1:a'\ndb: b\nadmin_user_id: 1\n\
+             sentry_url: https://key@sentry.example.com/1\ntraces_sample_rate: 0.5\n\
+             redis:\n  address: redis\ncache: 10.0.0.2\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.redis.address, "redis");
+        assert_eq!(cfg.sentry.unwrap().traces_sample_rate, 0.5);
+
+        let env = [
+            ("WEDNESDAY_CACHE", "10.0.0.3"),
+            ("WEDNESDAY_SENTRY_URL", "https://key@sentry.example.com/1"),
+            ("WEDNESDAY_TRACES_SAMPLE_RATE", "0.25"),
+            ("WEDNESDAY_COIN_MARKET_API_KEY", "env-key"),
+        ];
+        let cfg = Cfg::load(
+            config::Config::builder()
+                .add_source(File::from_str(
+                    "bot_name: a\ntoken: '1:a'\ndb: b\nadmin_user_id: 1\n",
+                    FileFormat::Yaml,
+                ))
+                .add_source(
+                    Environment::with_prefix("WEDNESDAY")
+                        .prefix_separator("_")
+                        .separator("__")
+                        .source(Some(
+                            env.iter()
+                                .map(|(k, v)| (k.to_string(), v.to_string()))
+                                .collect(),
+                        )),
+                ),
+        )
+        .unwrap();
+        assert_eq!(cfg.redis.address, "10.0.0.3");
+        assert_eq!(cfg.sentry.unwrap().traces_sample_rate, 0.25);
+        assert_eq!(cfg.coinmarketcap.unwrap().api_key, "env-key");
+    }
+}
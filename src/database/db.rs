@@ -1,10 +1,29 @@
 use std::collections::HashMap;
 
 use anyhow::Result;
-use sqlx::Row;
+use chrono::{DateTime, NaiveTime, Utc};
+
+use super::Topic;
+use crate::admins::{Admin, AdminRole};
+use crate::alerts::{Alert, AlertKind, AlertRequest};
+use crate::campaigns::{Campaign, CampaignStatus, CampaignTarget, Report};
+use crate::chart::{Candle, Resolution};
+use crate::delivery::{DeliveryStatus, Outcome};
+use crate::dump::{ChatDump, Dump, MappingEntry};
+use crate::members::ChatMember;
+use crate::scheduler::{CatchUp, ScheduleEntry};
+use crate::tickets::{Ticket, TicketStatus};
+use crate::toads::{NextToad, RotationToad, Toad, ToadChat, ToadRotation};
 
 pub type Pool = sqlx::PgPool;
 
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub topic: Topic,
+    pub created_at: DateTime<Utc>,
+    pub created_by: Option<i64>,
+}
+
 pub struct Database {
     pool: Pool,
 }
@@ -21,80 +40,313 @@ impl Database {
     }
 
     #[tracing::instrument(skip(self))]
-    pub async fn is_active(&self, chat_id: i64) -> Result<bool> {
+    pub async fn is_subscribed(&self, chat_id: i64, topic: Topic) -> Result<bool> {
         let row = sqlx::query!(
-            r#"SELECT chat_id FROM chats WHERE 'wednesday' = ANY(enabled_notifications)"#,
+            r#"SELECT chat_id FROM subscriptions WHERE chat_id = $1 AND topic = $2"#,
+            chat_id,
+            topic as Topic,
         )
         .fetch_optional(&self.pool)
         .await?;
         Ok(row.is_some())
     }
 
-    #[tracing::instrument(skip(self), fields(query))]
-    pub async fn add(&self, chat_id: i64) -> Result<()> {
-        sqlx::query!(r#"SELECT add_chat($1, 'wednesday')"#, chat_id)
-            .execute(&self.pool)
-            .await?;
-        Ok(())
+    /// Returns `false` if the chat was already subscribed to the topic.
+    #[tracing::instrument(skip(self))]
+    pub async fn subscribe(
+        &self,
+        chat_id: i64,
+        topic: Topic,
+        created_by: Option<i64>,
+    ) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"INSERT INTO chats (chat_id) VALUES ($1) ON CONFLICT DO NOTHING"#,
+            chat_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        let result = sqlx::query!(
+            r#"INSERT INTO subscriptions (chat_id, topic, created_by) VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING"#,
+            chat_id,
+            topic as Topic,
+            created_by,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
     }
 
-    #[tracing::instrument(skip(self), fields(query))]
-    pub async fn remove(&self, chat_id: i64) -> Result<()> {
-        sqlx::query!(r#"SELECT remove_chat($1, 'wednesday')"#, chat_id)
+    /// Returns `false` if the chat wasn't subscribed to the topic.
+    #[tracing::instrument(skip(self))]
+    pub async fn unsubscribe(&self, chat_id: i64, topic: Topic) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"DELETE FROM subscriptions WHERE chat_id = $1 AND topic = $2"#,
+            chat_id,
+            topic as Topic,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns the number of topics the chat was subscribed to.
+    #[tracing::instrument(skip(self))]
+    pub async fn unsubscribe_all(&self, chat_id: i64) -> Result<u64> {
+        let result = sqlx::query!(r#"DELETE FROM subscriptions WHERE chat_id = $1"#, chat_id)
             .execute(&self.pool)
             .await?;
-        Ok(())
+        Ok(result.rows_affected())
     }
 
-    #[tracing::instrument(skip(self), fields(query))]
-    pub async fn get_all_active_chats(&self) -> Result<Vec<i64>> {
-        let active_chats = sqlx::query!(
-            r#"SELECT chat_id FROM chats WHERE 'wednesday' = ANY(enabled_notifications)"#,
+    #[tracing::instrument(skip(self))]
+    pub async fn get_subscribers(&self, topic: Topic) -> Result<Vec<i64>> {
+        let chats = sqlx::query!(
+            r#"SELECT chat_id FROM subscriptions WHERE topic = $1 ORDER BY created_at"#,
+            topic as Topic,
         )
         .fetch_all(&self.pool)
         .await?
         .iter()
         .map(|row| row.chat_id)
         .collect();
-        Ok(active_chats)
+        Ok(chats)
     }
 
-    #[tracing::instrument(skip(self), fields(query))]
-    pub async fn is_active_crypto(&self, chat_id: i64) -> Result<bool> {
-        let row = sqlx::query!(
-            r#"SELECT chat_id FROM chats WHERE 'crypto' = ANY(enabled_notifications)"#,
+    #[tracing::instrument(skip(self))]
+    pub async fn get_subscriptions(&self, chat_id: i64) -> Result<Vec<Subscription>> {
+        let subscriptions = sqlx::query_as!(
+            Subscription,
+            r#"SELECT topic AS "topic: Topic", created_at, created_by
+            FROM subscriptions WHERE chat_id = $1 ORDER BY created_at"#,
+            chat_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(subscriptions)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_toad_chats(&self) -> Result<Vec<ToadChat>> {
+        let chats = sqlx::query_as!(
+            ToadChat,
+            r#"SELECT chats.chat_id, timezone, toad_time, last_toad_at
+            FROM chats JOIN subscriptions ON subscriptions.chat_id = chats.chat_id
+            WHERE topic = 'wednesday' ORDER BY subscriptions.created_at"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(chats)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_toad_chat(&self, chat_id: i64) -> Result<Option<ToadChat>> {
+        let chat = sqlx::query_as!(
+            ToadChat,
+            r#"SELECT chat_id, timezone, toad_time, last_toad_at FROM chats WHERE chat_id = $1"#,
+            chat_id
         )
         .fetch_optional(&self.pool)
         .await?;
-        Ok(row.is_some())
+        Ok(chat)
     }
 
-    #[tracing::instrument(skip(self), fields(query))]
-    pub async fn add_crypto(&self, chat_id: i64) -> Result<()> {
-        sqlx::query!(r#"SELECT add_chat($1, 'crypto')"#, chat_id)
-            .execute(&self.pool)
+    #[tracing::instrument(skip(self))]
+    pub async fn set_chat_timezone(&self, chat_id: i64, timezone: &str) -> Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO chats (chat_id, timezone) VALUES ($1, $2)
+            ON CONFLICT (chat_id) DO UPDATE SET timezone = excluded.timezone"#,
+            chat_id,
+            timezone,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn set_chat_toad_time(&self, chat_id: i64, toad_time: NaiveTime) -> Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO chats (chat_id, toad_time) VALUES ($1, $2)
+            ON CONFLICT (chat_id) DO UPDATE SET toad_time = excluded.toad_time"#,
+            chat_id,
+            toad_time,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Moves the chat with its settings, subscriptions, toad history, alerts and members
+    /// to the id of the supergroup it was migrated to. Subscriptions the new id already
+    /// has are kept, its other state is replaced.
+    #[tracing::instrument(skip(self))]
+    pub async fn migrate_chat(&self, from: i64, to: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let moved = sqlx::query!(r#"SELECT chat_id FROM chats WHERE chat_id = $1"#, from)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if moved {
+            sqlx::query!(
+                r#"INSERT INTO subscriptions (chat_id, topic, created_at, created_by)
+                SELECT $1, topic, created_at, created_by FROM subscriptions WHERE chat_id = $2
+                ON CONFLICT DO NOTHING"#,
+                from,
+                to,
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(r#"DELETE FROM chats WHERE chat_id = $1"#, to)
+                .execute(&mut *tx)
+                .await?;
+            // Subscriptions and deliveries follow by ON UPDATE CASCADE
+            sqlx::query!(
+                r#"UPDATE chats SET chat_id = $2 WHERE chat_id = $1"#,
+                from,
+                to
+            )
+            .execute(&mut *tx)
             .await?;
+        }
+        sqlx::query!(
+            r#"UPDATE alerts SET chat_id = $2 WHERE chat_id = $1"#,
+            from,
+            to
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"DELETE FROM chat_members WHERE chat_id = $1
+            AND user_id IN (SELECT user_id FROM chat_members WHERE chat_id = $2)"#,
+            from,
+            to
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"UPDATE chat_members SET chat_id = $2 WHERE chat_id = $1"#,
+            from,
+            to
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn mark_toad_sent(&self, chat_id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"UPDATE chats SET last_toad_at = now() WHERE chat_id = $1"#,
+            chat_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_toads(&self) -> Result<Vec<Toad>> {
+        let toads = sqlx::query_as!(
+            Toad,
+            r#"SELECT id, video_id, weight FROM toads ORDER BY id"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(toads)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_toad_rotation(&self, chat_id: i64) -> Result<ToadRotation> {
+        let cycle = sqlx::query!(
+            r#"SELECT max(cycle) AS cycle FROM toad_deliveries WHERE chat_id = $1"#,
+            chat_id
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .cycle
+        .unwrap_or_default();
+
+        let toads = sqlx::query!(
+            r#"SELECT id, video_id, weight, created_at,
+                EXISTS(
+                    SELECT 1 FROM toad_deliveries
+                    WHERE chat_id = $1 AND toad_id = toads.id AND cycle = $2
+                ) AS "sent_in_cycle!",
+                (SELECT max(sent_at) FROM toad_deliveries WHERE chat_id = $1 AND toad_id = toads.id) AS last_sent_at
+            FROM toads"#,
+            chat_id,
+            cycle,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| RotationToad {
+            toad: Toad {
+                id: row.id,
+                video_id: row.video_id,
+                weight: row.weight,
+            },
+            created_at: row.created_at,
+            sent_in_cycle: row.sent_in_cycle,
+            last_sent_at: row.last_sent_at,
+        })
+        .collect();
+
+        Ok(ToadRotation { cycle, toads })
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn add_toad_delivery(&self, chat_id: i64, toad: &NextToad) -> Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO toad_deliveries (chat_id, toad_id, cycle) VALUES ($1, $2, $3)"#,
+            chat_id,
+            toad.toad.id,
+            toad.cycle,
+        )
+        .execute(&self.pool)
+        .await?;
         Ok(())
     }
 
-    #[tracing::instrument(skip(self), fields(query))]
-    pub async fn remove_crypto(&self, chat_id: i64) -> Result<()> {
-        sqlx::query!(r#"SELECT remove_chat($1, 'crypto')"#, chat_id)
+    /// Returns `None` if the video is already in the catalog.
+    #[tracing::instrument(skip(self))]
+    pub async fn add_toad(
+        &self,
+        video_id: &str,
+        weight: f64,
+        created_by: Option<i64>,
+    ) -> Result<Option<i32>> {
+        let row = sqlx::query!(
+            r#"INSERT INTO toads (video_id, weight, created_by) VALUES ($1, $2, $3)
+            ON CONFLICT (video_id) DO NOTHING
+            RETURNING id"#,
+            video_id,
+            weight,
+            created_by,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|row| row.id))
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn set_toad_weight(&self, id: i32, weight: f64) -> Result<bool> {
+        let result = sqlx::query!(r#"UPDATE toads SET weight = $2 WHERE id = $1"#, id, weight)
             .execute(&self.pool)
             .await?;
-        Ok(())
+        Ok(result.rows_affected() > 0)
     }
 
-    #[tracing::instrument(skip(self), fields(query))]
-    pub async fn get_all_active_crypto_chats(&self) -> Result<Vec<i64>> {
-        let active_chats =
-            sqlx::query(r#"SELECT chat_id FROM chats WHERE 'crypto' = ANY(enabled_notifications)"#)
-                .fetch_all(&self.pool)
-                .await?
-                .iter()
-                .map(|row| row.get(0))
-                .collect();
-        Ok(active_chats)
+    #[tracing::instrument(skip(self))]
+    pub async fn remove_toad(&self, id: i32) -> Result<bool> {
+        let result = sqlx::query!(r#"DELETE FROM toads WHERE id = $1"#, id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
     }
 
     #[tracing::instrument(skip(self))]
@@ -117,4 +369,734 @@ impl Database {
             .collect();
         Ok(mapping)
     }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn export_dump(&self) -> Result<Dump> {
+        let chats = sqlx::query_as!(
+            ChatDump,
+            r#"SELECT chat_id, timezone, toad_time,
+                ARRAY(
+                    SELECT topic FROM subscriptions
+                    WHERE subscriptions.chat_id = chats.chat_id ORDER BY topic
+                ) AS "topics!: Vec<Topic>"
+            FROM chats ORDER BY chat_id"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mapping = sqlx::query_as!(
+            MappingEntry,
+            r#"SELECT user_id, username FROM mapping ORDER BY user_id"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(Dump { chats, mapping })
+    }
+
+    /// Merges the dump into the database, existing chats get its settings and topics.
+    #[tracing::instrument(skip(self, dump))]
+    pub async fn import_dump(&self, dump: &Dump) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for chat in &dump.chats {
+            sqlx::query!(
+                r#"INSERT INTO chats (chat_id, timezone, toad_time) VALUES ($1, $2, $3)
+                ON CONFLICT (chat_id) DO UPDATE
+                SET timezone = EXCLUDED.timezone, toad_time = EXCLUDED.toad_time"#,
+                chat.chat_id,
+                chat.timezone,
+                chat.toad_time,
+            )
+            .execute(&mut *tx)
+            .await?;
+            for topic in &chat.topics {
+                sqlx::query!(
+                    r#"INSERT INTO subscriptions (chat_id, topic) VALUES ($1, $2)
+                    ON CONFLICT DO NOTHING"#,
+                    chat.chat_id,
+                    *topic as Topic,
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        for entry in &dump.mapping {
+            sqlx::query!(
+                r#"SELECT update_mapping($1, $2)"#,
+                entry.user_id,
+                entry.username
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn touch_chat_member(&self, chat_id: i64, user_id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO chat_members (chat_id, user_id) VALUES ($1, $2)
+            ON CONFLICT (chat_id, user_id) DO UPDATE SET last_seen = now()"#,
+            chat_id,
+            user_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn remove_chat_member(&self, chat_id: i64, user_id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"DELETE FROM chat_members WHERE chat_id = $1 AND user_id = $2"#,
+            chat_id,
+            user_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Members of the chat who haven't opted out of `/all`.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_mentionable_members(&self, chat_id: i64) -> Result<Vec<ChatMember>> {
+        let members = sqlx::query_as!(
+            ChatMember,
+            r#"SELECT chat_members.user_id, mapping.username AS "name?"
+            FROM chat_members LEFT JOIN mapping ON mapping.user_id = chat_members.user_id
+            WHERE chat_id = $1 AND NOT mention_opt_out
+            ORDER BY last_seen DESC"#,
+            chat_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(members)
+    }
+
+    /// Flips the `/all` opt-out of the member, returns whether the member is opted out now.
+    #[tracing::instrument(skip(self))]
+    pub async fn toggle_mention_opt_out(&self, chat_id: i64, user_id: i64) -> Result<bool> {
+        let row = sqlx::query!(
+            r#"INSERT INTO chat_members (chat_id, user_id, mention_opt_out) VALUES ($1, $2, true)
+            ON CONFLICT (chat_id, user_id) DO UPDATE
+            SET mention_opt_out = NOT chat_members.mention_opt_out, last_seen = now()
+            RETURNING mention_opt_out"#,
+            chat_id,
+            user_id,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.mention_opt_out)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn add_delivery_log(
+        &self,
+        chat_id: i64,
+        kind: &str,
+        outcome: &Outcome,
+        attempts: i32,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO delivery_log (chat_id, kind, status, attempts, error)
+            VALUES ($1, $2, $3, $4, $5)"#,
+            chat_id,
+            kind,
+            outcome.status() as DeliveryStatus,
+            attempts,
+            outcome.error(),
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Claims the chats which haven't got their campaign yet and returns them.
+    ///
+    /// Sent and blocked chats are never claimed again, the ones being sent are claimed
+    /// only when they look abandoned, failed ones until `max_attempts` is reached. A failed
+    /// chat waits `retry_after` before the second attempt, twice as long before each next one.
+    #[tracing::instrument(skip(self, deliveries))]
+    pub async fn claim_deliveries(
+        &self,
+        deliveries: &[(String, i64)],
+        max_attempts: i32,
+        retry_after: std::time::Duration,
+    ) -> Result<Vec<i64>> {
+        let (campaigns, chats): (Vec<String>, Vec<i64>) = deliveries.iter().cloned().unzip();
+        let claimed = sqlx::query!(
+            r#"INSERT INTO deliveries (campaign, chat_id)
+            SELECT * FROM UNNEST($1::text[], $2::bigint[])
+            ON CONFLICT (campaign, chat_id) DO UPDATE
+            SET status = NULL, attempts = deliveries.attempts + 1, updated_at = now()
+            WHERE deliveries.attempts < $3 AND (
+                deliveries.status = 'failed' AND deliveries.updated_at
+                    < now() - make_interval(secs => $4 * power(2, deliveries.attempts - 1))
+                OR deliveries.status IS NULL AND deliveries.updated_at < now() - interval '10 minutes'
+            )
+            RETURNING chat_id"#,
+            &campaigns,
+            &chats,
+            max_attempts,
+            retry_after.as_secs_f64(),
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| row.chat_id)
+        .collect();
+        Ok(claimed)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn finish_delivery(
+        &self,
+        campaign: &str,
+        chat_id: i64,
+        toad_id: Option<i32>,
+        outcome: &Outcome,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"UPDATE deliveries SET status = $3, toad_id = $4, error = $5, updated_at = now()
+            WHERE campaign = $1 AND chat_id = $2"#,
+            campaign,
+            chat_id,
+            outcome.status() as DeliveryStatus,
+            toad_id,
+            outcome.error(),
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_target_chats(&self, target: CampaignTarget) -> Result<Vec<i64>> {
+        let chats = match target.topic() {
+            Some(topic) => self.get_subscribers(topic).await?,
+            None => sqlx::query!(r#"SELECT DISTINCT chat_id FROM subscriptions ORDER BY chat_id"#)
+                .fetch_all(&self.pool)
+                .await?
+                .iter()
+                .map(|row| row.chat_id)
+                .collect(),
+        };
+        Ok(chats)
+    }
+
+    #[tracing::instrument(skip(self, text))]
+    pub async fn create_campaign(
+        &self,
+        target: CampaignTarget,
+        text: &str,
+        photo_file_id: Option<&str>,
+        created_by: Option<i64>,
+    ) -> Result<i64> {
+        let row = sqlx::query!(
+            r#"INSERT INTO campaigns (target, text, photo_file_id, created_by)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id"#,
+            target as CampaignTarget,
+            text,
+            photo_file_id,
+            created_by,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.id)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_campaign(&self, id: i64) -> Result<Option<Campaign>> {
+        let campaign = sqlx::query_as!(
+            Campaign,
+            r#"SELECT id, target AS "target: CampaignTarget", text, photo_file_id,
+                status AS "status: CampaignStatus", created_at, sent, failed, blocked
+            FROM campaigns WHERE id = $1"#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(campaign)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_campaigns(&self, limit: i64) -> Result<Vec<Campaign>> {
+        let campaigns = sqlx::query_as!(
+            Campaign,
+            r#"SELECT id, target AS "target: CampaignTarget", text, photo_file_id,
+                status AS "status: CampaignStatus", created_at, sent, failed, blocked
+            FROM campaigns ORDER BY id DESC LIMIT $1"#,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(campaigns)
+    }
+
+    /// Moves a draft to the given status, `false` if the campaign isn't a draft anymore.
+    #[tracing::instrument(skip(self))]
+    pub async fn leave_campaign_draft(&self, id: i64, status: CampaignStatus) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"UPDATE campaigns SET status = $2,
+                started_at = CASE WHEN $2 = 'sending'::campaign_status THEN now() END
+            WHERE id = $1 AND status = 'draft'"#,
+            id,
+            status as CampaignStatus,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn finish_campaign(&self, id: i64, report: &Report) -> Result<()> {
+        sqlx::query!(
+            r#"UPDATE campaigns SET status = 'done', finished_at = now(),
+                sent = $2, failed = $3, blocked = $4
+            WHERE id = $1"#,
+            id,
+            report.sent,
+            report.failed,
+            report.blocked,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the open ticket of the user and whether it was just created.
+    #[tracing::instrument(skip(self))]
+    pub async fn open_ticket(&self, user_id: i64) -> Result<(i64, bool)> {
+        // xmax is zero only for freshly inserted rows
+        let row = sqlx::query!(
+            r#"INSERT INTO tickets (user_id) VALUES ($1)
+            ON CONFLICT (user_id) WHERE status = 'open' DO UPDATE SET updated_at = now()
+            RETURNING id, (xmax = 0) AS "created!""#,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok((row.id, row.created))
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn add_ticket_message(
+        &self,
+        ticket_id: i64,
+        admin_chat_id: i64,
+        admin_message_id: i32,
+        user_message_id: i32,
+        outgoing: bool,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO ticket_messages (admin_chat_id, admin_message_id, ticket_id, user_message_id, outgoing)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (admin_chat_id, admin_message_id) DO NOTHING"#,
+            admin_chat_id,
+            admin_message_id,
+            ticket_id,
+            user_message_id,
+            outgoing,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query!(
+            r#"UPDATE tickets SET updated_at = now() WHERE id = $1"#,
+            ticket_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Ticket of a message in an admin chat, so the admin can answer with a reply.
+    /// Messages without a chat were relayed to `owner_id` before there were several admins.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_ticket_by_admin_message(
+        &self,
+        admin_chat_id: i64,
+        admin_message_id: i32,
+        owner_id: i64,
+    ) -> Result<Option<Ticket>> {
+        let ticket = sqlx::query_as!(
+            Ticket,
+            r#"SELECT tickets.id, tickets.user_id, mapping.username AS "username?",
+                status AS "status: TicketStatus", updated_at
+            FROM ticket_messages
+            JOIN tickets ON tickets.id = ticket_messages.ticket_id
+            LEFT JOIN mapping ON mapping.user_id = tickets.user_id
+            WHERE COALESCE(admin_chat_id, $3) = $1 AND admin_message_id = $2"#,
+            admin_chat_id,
+            admin_message_id,
+            owner_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(ticket)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_open_tickets(&self) -> Result<Vec<Ticket>> {
+        let tickets = sqlx::query_as!(
+            Ticket,
+            r#"SELECT tickets.id, tickets.user_id, mapping.username AS "username?",
+                status AS "status: TicketStatus", updated_at
+            FROM tickets LEFT JOIN mapping ON mapping.user_id = tickets.user_id
+            WHERE status = 'open'
+            ORDER BY updated_at DESC"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tickets)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn close_ticket(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"UPDATE tickets SET status = 'closed', closed_at = now()
+            WHERE id = $1 AND status = 'open'"#,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_admin_role(&self, user_id: i64) -> Result<Option<AdminRole>> {
+        let row = sqlx::query!(
+            r#"SELECT role AS "role: AdminRole" FROM admins WHERE user_id = $1"#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|row| row.role))
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_admins(&self) -> Result<Vec<Admin>> {
+        let admins = sqlx::query_as!(
+            Admin,
+            r#"SELECT admins.user_id, mapping.username AS "username?", role AS "role: AdminRole"
+            FROM admins LEFT JOIN mapping ON mapping.user_id = admins.user_id
+            ORDER BY granted_at"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(admins)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn grant_admin(
+        &self,
+        user_id: i64,
+        role: AdminRole,
+        granted_by: Option<i64>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO admins (user_id, role, granted_by) VALUES ($1, $2, $3)
+            ON CONFLICT (user_id) DO UPDATE
+            SET role = excluded.role, granted_by = excluded.granted_by, granted_at = now()"#,
+            user_id,
+            role as AdminRole,
+            granted_by,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn revoke_admin(&self, user_id: i64) -> Result<bool> {
+        let result = sqlx::query!(r#"DELETE FROM admins WHERE user_id = $1"#, user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Looks the user up in the mapping, the leading `@` is optional.
+    #[tracing::instrument(skip(self))]
+    pub async fn find_user_id(&self, username: &str) -> Result<Option<i64>> {
+        let row = sqlx::query!(
+            r#"SELECT user_id FROM mapping WHERE lower(username) = lower($1)"#,
+            username.trim_start_matches('@')
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|row| row.user_id))
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn add_alert(
+        &self,
+        chat_id: i64,
+        created_by: Option<i64>,
+        alert: &AlertRequest,
+        reference_price: f64,
+    ) -> Result<i64> {
+        let row = sqlx::query!(
+            r#"INSERT INTO alerts (chat_id, created_by, coin, kind, threshold, window_secs, reference_price, reference_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+            RETURNING id"#,
+            chat_id,
+            created_by,
+            alert.coin,
+            alert.kind as AlertKind,
+            alert.threshold,
+            alert.window.map(|window| window.num_seconds()),
+            reference_price,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.id)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_alerts(&self, chat_id: i64) -> Result<Vec<Alert>> {
+        let alerts = sqlx::query_as!(
+            Alert,
+            r#"SELECT id, chat_id, coin, kind AS "kind: AlertKind", threshold, window_secs, reference_price, reference_at
+            FROM alerts WHERE chat_id = $1 AND fired_at IS NULL ORDER BY id"#,
+            chat_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(alerts)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_active_alerts(&self) -> Result<Vec<Alert>> {
+        let alerts = sqlx::query_as!(
+            Alert,
+            r#"SELECT id, chat_id, coin, kind AS "kind: AlertKind", threshold, window_secs, reference_price, reference_at
+            FROM alerts WHERE fired_at IS NULL ORDER BY id"#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(alerts)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn remove_alert(&self, chat_id: i64, id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"DELETE FROM alerts WHERE id = $1 AND chat_id = $2 AND fired_at IS NULL"#,
+            id,
+            chat_id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns `false` if the alert was already fired, so it is sent only once.
+    #[tracing::instrument(skip(self))]
+    pub async fn fire_alert(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"UPDATE alerts SET fired_at = now() WHERE id = $1 AND fired_at IS NULL"#,
+            id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn rebase_alert(&self, id: i64, reference_price: f64) -> Result<()> {
+        sqlx::query!(
+            r#"UPDATE alerts SET reference_price = $2, reference_at = now() WHERE id = $1"#,
+            id,
+            reference_price,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn add_price_tick(&self, coin: &str, source: &str, price: f64) -> Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO price_ticks (coin, source, price) VALUES ($1, $2, $3)"#,
+            coin,
+            source,
+            price,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Rebuilds hourly candles from the ticks and daily candles from the hourly ones
+    /// for every bucket starting from `since`.
+    #[tracing::instrument(skip(self))]
+    pub async fn downsample_prices(&self, since: DateTime<Utc>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"INSERT INTO price_candles (coin, resolution, bucket, open, high, low, close)
+            SELECT coin, 'hour', date_trunc('hour', fetched_at) AS hour,
+                (array_agg(price ORDER BY fetched_at))[1],
+                max(price),
+                min(price),
+                (array_agg(price ORDER BY fetched_at DESC))[1]
+            FROM price_ticks
+            WHERE fetched_at >= date_trunc('hour', $1::timestamptz)
+            GROUP BY coin, hour
+            ON CONFLICT (coin, resolution, bucket) DO UPDATE
+            SET open = excluded.open, high = excluded.high, low = excluded.low, close = excluded.close"#,
+            since,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"INSERT INTO price_candles (coin, resolution, bucket, open, high, low, close)
+            SELECT coin, 'day', date_trunc('day', bucket) AS day,
+                (array_agg(open ORDER BY bucket))[1],
+                max(high),
+                min(low),
+                (array_agg(close ORDER BY bucket DESC))[1]
+            FROM price_candles
+            WHERE resolution = 'hour' AND bucket >= date_trunc('day', $1::timestamptz)
+            GROUP BY coin, day
+            ON CONFLICT (coin, resolution, bucket) DO UPDATE
+            SET open = excluded.open, high = excluded.high, low = excluded.low, close = excluded.close"#,
+            since,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Removes ticks and hourly candles older than the given points, daily candles are kept forever.
+    #[tracing::instrument(skip(self))]
+    pub async fn prune_price_history(
+        &self,
+        ticks_before: DateTime<Utc>,
+        hourly_candles_before: DateTime<Utc>,
+    ) -> Result<(u64, u64)> {
+        let ticks = sqlx::query!(
+            r#"DELETE FROM price_ticks WHERE fetched_at < $1"#,
+            ticks_before
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        let candles = sqlx::query!(
+            r#"DELETE FROM price_candles WHERE resolution = 'hour' AND bucket < $1"#,
+            hourly_candles_before
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok((ticks, candles))
+    }
+
+    /// The latest known price of the coin at or before `at`, taken from the most
+    /// detailed data still kept for that moment.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_price_at(
+        &self,
+        coin: &str,
+        at: DateTime<Utc>,
+    ) -> Result<Option<(DateTime<Utc>, f64)>> {
+        let row = sqlx::query!(
+            r#"SELECT at AS "at!", price AS "price!" FROM (
+                (SELECT fetched_at AS at, price, 0 AS rank FROM price_ticks
+                WHERE coin = $1 AND fetched_at <= $2 ORDER BY fetched_at DESC LIMIT 1)
+                UNION ALL
+                (SELECT bucket, open, 1 FROM price_candles
+                WHERE coin = $1 AND resolution = 'hour' AND bucket <= $2 ORDER BY bucket DESC LIMIT 1)
+                UNION ALL
+                (SELECT bucket, open, 2 FROM price_candles
+                WHERE coin = $1 AND resolution = 'day' AND bucket <= $2 ORDER BY bucket DESC LIMIT 1)
+            ) prices ORDER BY at DESC, rank LIMIT 1"#,
+            coin,
+            at,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|row| (row.at, row.price)))
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_candles(
+        &self,
+        coin: &str,
+        resolution: Resolution,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        let candles = sqlx::query_as!(
+            Candle,
+            r#"SELECT bucket, open, high, low, close FROM price_candles
+            WHERE coin = $1 AND resolution = $2 AND bucket >= $3 ORDER BY bucket"#,
+            coin,
+            resolution as Resolution,
+            since,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(candles)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_schedules(&self) -> Result<Vec<ScheduleEntry>> {
+        let schedules = sqlx::query_as!(
+            ScheduleEntry,
+            r#"SELECT job, cron, timezone, enabled, catch_up AS "catch_up: CatchUp", last_run_at
+            FROM schedules ORDER BY job"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(schedules)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn set_schedule(&self, job: &str, cron: &str, timezone: &str) -> Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO schedules (job, cron, timezone) VALUES ($1, $2, $3)
+            ON CONFLICT (job) DO UPDATE SET cron = excluded.cron, timezone = excluded.timezone"#,
+            job,
+            cron,
+            timezone,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns `false` if there is no schedule for the job.
+    #[tracing::instrument(skip(self))]
+    pub async fn set_schedule_enabled(&self, job: &str, enabled: bool) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"UPDATE schedules SET enabled = $2 WHERE job = $1"#,
+            job,
+            enabled,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn set_schedule_catch_up(&self, job: &str, catch_up: CatchUp) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"UPDATE schedules SET catch_up = $2 WHERE job = $1"#,
+            job,
+            catch_up as CatchUp,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn mark_schedule_run(&self, job: &str, at: DateTime<Utc>) -> Result<()> {
+        sqlx::query!(
+            r#"UPDATE schedules SET last_run_at = $2 WHERE job = $1"#,
+            job,
+            at,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
 }
@@ -1,3 +1,5 @@
 mod db;
+mod topic;
 
 pub use db::{Database, Pool};
+pub use topic::Topic;
@@ -0,0 +1,38 @@
+ALTER TABLE chats ADD COLUMN enabled_notifications topic[];
+
+UPDATE chats
+SET enabled_notifications = (
+    SELECT array_agg(topic) FROM subscriptions WHERE subscriptions.chat_id = chats.chat_id
+);
+
+DROP TABLE "subscriptions";
+
+ALTER TYPE topic RENAME TO notify_type;
+
+create function add_chat(_chat_id bigint, _notify notify_type) returns void as $$
+declare
+	_exists bool;
+begin
+    select exists(select chat_id from chats where chat_id = _chat_id) into _exists;
+	if _exists then
+		update chats
+		set enabled_notifications = array_append(enabled_notifications, _notify)
+		where _chat_id = chat_id and not _notify = any(enabled_notifications);
+	else
+		insert into chats (chat_id, enabled_notifications) values (_chat_id, ARRAY[_notify]);
+	end if;
+end;
+$$ language plpgsql;
+
+create function remove_chat(_chat_id bigint, _notify notify_type) returns void as $$
+declare
+	_exists bool;
+begin
+    select exists(select chat_id from chats where chat_id = _chat_id) into _exists;
+	if _exists then
+		update chats
+		set enabled_notifications = array_remove(enabled_notifications, _notify)
+		where chat_id = _chat_id and _notify = any(enabled_notifications);
+	end if;
+end;
+$$ language plpgsql;
@@ -0,0 +1,20 @@
+ALTER TYPE notify_type RENAME TO topic;
+
+CREATE TABLE "subscriptions" (
+    chat_id bigint NOT NULL REFERENCES chats (chat_id) ON DELETE CASCADE,
+    topic topic NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    created_by bigint,
+    PRIMARY KEY (chat_id, topic)
+);
+
+INSERT INTO subscriptions (chat_id, topic)
+SELECT DISTINCT chat_id, unnest(enabled_notifications)
+FROM chats
+WHERE enabled_notifications IS NOT NULL
+ON CONFLICT DO NOTHING;
+
+DROP FUNCTION IF EXISTS add_chat;
+DROP FUNCTION IF EXISTS remove_chat;
+
+ALTER TABLE chats DROP COLUMN enabled_notifications;
@@ -0,0 +1,2 @@
+DROP TABLE "alerts";
+DROP TYPE alert_kind;
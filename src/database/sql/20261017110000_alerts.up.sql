@@ -0,0 +1,18 @@
+CREATE TYPE alert_kind AS ENUM ('above', 'below', 'change');
+
+CREATE TABLE "alerts" (
+    id bigserial PRIMARY KEY,
+    chat_id bigint NOT NULL,
+    created_by bigint,
+    coin text NOT NULL,
+    kind alert_kind NOT NULL,
+    threshold double precision NOT NULL,
+    window_secs bigint,
+    reference_price double precision,
+    reference_at timestamptz,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    fired_at timestamptz
+);
+
+CREATE INDEX alerts_chat_id_idx ON alerts (chat_id);
+CREATE INDEX alerts_active_idx ON alerts (coin) WHERE fired_at IS NULL;
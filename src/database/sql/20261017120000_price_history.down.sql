@@ -0,0 +1,3 @@
+DROP TABLE "price_candles";
+DROP TYPE candle_resolution;
+DROP TABLE "price_ticks";
@@ -0,0 +1,22 @@
+CREATE TABLE "price_ticks" (
+    id bigserial PRIMARY KEY,
+    coin text NOT NULL,
+    source text NOT NULL,
+    price double precision NOT NULL,
+    fetched_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX price_ticks_coin_fetched_at_idx ON price_ticks (coin, fetched_at);
+
+CREATE TYPE candle_resolution AS ENUM ('hour', 'day');
+
+CREATE TABLE "price_candles" (
+    coin text NOT NULL,
+    resolution candle_resolution NOT NULL,
+    bucket timestamptz NOT NULL,
+    open double precision NOT NULL,
+    high double precision NOT NULL,
+    low double precision NOT NULL,
+    close double precision NOT NULL,
+    PRIMARY KEY (coin, resolution, bucket)
+);
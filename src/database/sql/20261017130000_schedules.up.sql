@@ -0,0 +1,14 @@
+CREATE TABLE "schedules" (
+    job text PRIMARY KEY,
+    cron text NOT NULL,
+    timezone text NOT NULL DEFAULT 'UTC',
+    enabled boolean NOT NULL DEFAULT true
+);
+
+INSERT INTO schedules (job, cron, timezone) VALUES
+    ('wednesday', '0 0 9 * * Wed', 'Europe/Moscow'),
+    ('crypto', '0 0 6,18 * * *', 'Europe/Moscow'),
+    ('rate_check', '0 */10 * * * *', 'UTC'),
+    ('alerts', '0 * * * * *', 'UTC'),
+    ('price_history', '0 5 * * * *', 'UTC'),
+    ('heartbeat', '0 0 * * * *', 'UTC');
@@ -0,0 +1,6 @@
+UPDATE schedules SET cron = '0 0 9 * * Wed', timezone = 'Europe/Moscow' WHERE job = 'wednesday';
+
+ALTER TABLE chats
+    DROP COLUMN last_toad_at,
+    DROP COLUMN toad_time,
+    DROP COLUMN timezone;
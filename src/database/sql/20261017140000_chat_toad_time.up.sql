@@ -0,0 +1,7 @@
+ALTER TABLE chats
+    ADD COLUMN timezone text NOT NULL DEFAULT 'Europe/Moscow',
+    ADD COLUMN toad_time time NOT NULL DEFAULT '09:00',
+    ADD COLUMN last_toad_at timestamptz;
+
+-- Toads are sent per chat at its local time, the job only looks for the chats that are due
+UPDATE schedules SET cron = '0 * * * * *', timezone = 'UTC' WHERE job = 'wednesday';
@@ -0,0 +1,3 @@
+DROP TABLE "toad_deliveries";
+
+ALTER TABLE "toads" DROP COLUMN weight;
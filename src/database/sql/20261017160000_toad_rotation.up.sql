@@ -0,0 +1,10 @@
+ALTER TABLE "toads" ADD COLUMN weight double precision NOT NULL DEFAULT 1 CHECK (weight > 0);
+
+CREATE TABLE "toad_deliveries" (
+    chat_id bigint NOT NULL REFERENCES chats (chat_id) ON DELETE CASCADE,
+    toad_id integer NOT NULL REFERENCES toads (id) ON DELETE CASCADE,
+    cycle integer NOT NULL,
+    sent_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX toad_deliveries_chat_id_idx ON toad_deliveries (chat_id, cycle);
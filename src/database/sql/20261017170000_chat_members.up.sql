@@ -0,0 +1,7 @@
+CREATE TABLE "chat_members" (
+    chat_id bigint NOT NULL,
+    user_id bigint NOT NULL,
+    last_seen timestamptz NOT NULL DEFAULT now(),
+    mention_opt_out boolean NOT NULL DEFAULT false,
+    PRIMARY KEY (chat_id, user_id)
+);
@@ -0,0 +1,3 @@
+DROP TABLE "delivery_log";
+
+DROP TYPE delivery_status;
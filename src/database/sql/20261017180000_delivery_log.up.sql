@@ -0,0 +1,13 @@
+CREATE TYPE delivery_status AS ENUM ('sent', 'migrated', 'blocked', 'failed');
+
+CREATE TABLE "delivery_log" (
+    id bigserial PRIMARY KEY,
+    chat_id bigint NOT NULL,
+    kind text NOT NULL,
+    status delivery_status NOT NULL,
+    attempts integer NOT NULL,
+    error text,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX delivery_log_chat_id_idx ON delivery_log (chat_id, created_at);
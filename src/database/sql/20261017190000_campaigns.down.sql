@@ -0,0 +1,4 @@
+DROP TABLE "campaigns";
+
+DROP TYPE campaign_status;
+DROP TYPE campaign_target;
@@ -0,0 +1,17 @@
+CREATE TYPE campaign_target AS ENUM ('wednesday', 'crypto', 'all');
+CREATE TYPE campaign_status AS ENUM ('draft', 'sending', 'done', 'cancelled');
+
+CREATE TABLE "campaigns" (
+    id bigserial PRIMARY KEY,
+    target campaign_target NOT NULL,
+    text text NOT NULL,
+    photo_file_id text,
+    status campaign_status NOT NULL DEFAULT 'draft',
+    created_by bigint,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    started_at timestamptz,
+    finished_at timestamptz,
+    sent integer NOT NULL DEFAULT 0,
+    failed integer NOT NULL DEFAULT 0,
+    blocked integer NOT NULL DEFAULT 0
+);
@@ -0,0 +1,4 @@
+DROP TABLE "ticket_messages";
+DROP TABLE "tickets";
+
+DROP TYPE ticket_status;
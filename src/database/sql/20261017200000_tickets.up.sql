@@ -0,0 +1,23 @@
+CREATE TYPE ticket_status AS ENUM ('open', 'closed');
+
+CREATE TABLE "tickets" (
+    id bigserial PRIMARY KEY,
+    user_id bigint NOT NULL,
+    status ticket_status NOT NULL DEFAULT 'open',
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    closed_at timestamptz
+);
+
+-- A user has at most one open conversation
+CREATE UNIQUE INDEX tickets_open_user_id_idx ON tickets (user_id) WHERE status = 'open';
+
+CREATE TABLE "ticket_messages" (
+    admin_message_id integer PRIMARY KEY,
+    ticket_id bigint NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    user_message_id integer NOT NULL,
+    outgoing boolean NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ticket_messages_ticket_id_idx ON ticket_messages (ticket_id);
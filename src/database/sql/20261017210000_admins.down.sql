@@ -0,0 +1,10 @@
+DROP INDEX ticket_messages_admin_message_idx;
+DELETE FROM ticket_messages WHERE admin_chat_id IS NOT NULL AND admin_message_id IN (
+    SELECT admin_message_id FROM ticket_messages GROUP BY admin_message_id HAVING count(*) > 1
+);
+ALTER TABLE "ticket_messages" DROP COLUMN admin_chat_id;
+ALTER TABLE "ticket_messages" ADD PRIMARY KEY (admin_message_id);
+
+DROP TABLE "admins";
+
+DROP TYPE admin_role;
@@ -0,0 +1,14 @@
+CREATE TYPE admin_role AS ENUM ('owner', 'moderator', 'broadcaster');
+
+CREATE TABLE "admins" (
+    user_id bigint PRIMARY KEY,
+    role admin_role NOT NULL,
+    granted_by bigint,
+    granted_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- Tickets are relayed to every moderator, message ids are unique only within a chat.
+-- Messages relayed earlier went to the admin from the config and keep a NULL chat.
+ALTER TABLE "ticket_messages" DROP CONSTRAINT ticket_messages_pkey;
+ALTER TABLE "ticket_messages" ADD COLUMN admin_chat_id bigint;
+CREATE UNIQUE INDEX ticket_messages_admin_message_idx ON ticket_messages (admin_chat_id, admin_message_id);
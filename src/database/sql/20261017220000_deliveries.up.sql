@@ -0,0 +1,13 @@
+-- Ledger of broadcasts, a chat gets a campaign (e.g. the toad of a week) once
+CREATE TABLE "deliveries" (
+    campaign text NOT NULL,
+    chat_id bigint NOT NULL REFERENCES chats (chat_id) ON DELETE CASCADE,
+    -- NULL while the message is being sent
+    status delivery_status,
+    toad_id integer REFERENCES toads (id) ON DELETE SET NULL,
+    attempts integer NOT NULL DEFAULT 1,
+    error text,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (campaign, chat_id)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE schedules DROP COLUMN catch_up, DROP COLUMN last_run_at;
+DROP TYPE IF EXISTS catch_up;
@@ -0,0 +1,8 @@
+CREATE TYPE catch_up AS ENUM ('skip', 'once', 'all');
+
+ALTER TABLE schedules
+    ADD COLUMN catch_up catch_up NOT NULL DEFAULT 'skip',
+    ADD COLUMN last_run_at timestamptz;
+
+-- Due chats are picked by the job itself, one run is enough to send every missed toad
+UPDATE schedules SET catch_up = 'once' WHERE job IN ('wednesday', 'crypto', 'price_history');
@@ -0,0 +1,9 @@
+ALTER TABLE "subscriptions" DROP CONSTRAINT subscriptions_chat_id_fkey,
+    ADD CONSTRAINT subscriptions_chat_id_fkey FOREIGN KEY (chat_id)
+        REFERENCES chats (chat_id) ON DELETE CASCADE;
+ALTER TABLE "toad_deliveries" DROP CONSTRAINT toad_deliveries_chat_id_fkey,
+    ADD CONSTRAINT toad_deliveries_chat_id_fkey FOREIGN KEY (chat_id)
+        REFERENCES chats (chat_id) ON DELETE CASCADE;
+ALTER TABLE "deliveries" DROP CONSTRAINT deliveries_chat_id_fkey,
+    ADD CONSTRAINT deliveries_chat_id_fkey FOREIGN KEY (chat_id)
+        REFERENCES chats (chat_id) ON DELETE CASCADE;
@@ -0,0 +1,34 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "topic", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Topic {
+    Wednesday,
+    Crypto,
+}
+
+impl Topic {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Topic::Wednesday => "wednesday",
+            Topic::Crypto => "crypto",
+        }
+    }
+
+    /// Human readable name of the list a chat is added to.
+    pub fn list_name(&self) -> &'static str {
+        match self {
+            Topic::Wednesday => "list",
+            Topic::Crypto => "crypto list",
+        }
+    }
+}
+
+impl fmt::Display for Topic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use tokio::time::{Duration, Instant};
+
+/// Telegram allows about 30 messages per second overall.
+const GLOBAL_INTERVAL: Duration = Duration::from_millis(35);
+/// One message per second in a private chat.
+const PRIVATE_INTERVAL: Duration = Duration::from_secs(1);
+/// 20 messages per minute in a group.
+const GROUP_INTERVAL: Duration = Duration::from_secs(3);
+/// Forget chats which are idle, so the map doesn't grow forever.
+const CLEANUP_THRESHOLD: usize = 1024;
+
+/// Hands out send slots, so the fan-out doesn't hit Telegram flood control.
+#[derive(Debug)]
+pub struct Limiter {
+    next_global: Instant,
+    next_chat: HashMap<i64, Instant>,
+}
+
+impl Limiter {
+    pub fn new() -> Self {
+        Self {
+            next_global: Instant::now(),
+            next_chat: HashMap::new(),
+        }
+    }
+
+    fn chat_interval(chat_id: i64) -> Duration {
+        // Groups and channels have negative ids
+        if chat_id < 0 {
+            GROUP_INTERVAL
+        } else {
+            PRIVATE_INTERVAL
+        }
+    }
+
+    /// Reserves the earliest moment a message may be sent to the chat.
+    pub fn reserve(&mut self, chat_id: i64, now: Instant) -> Instant {
+        if self.next_chat.len() > CLEANUP_THRESHOLD {
+            self.next_chat.retain(|_, next| *next > now);
+        }
+
+        // The global pace is kept apart from the chat one, so a message which waits
+        // for its chat doesn't hold back messages to other chats
+        let global = now.max(self.next_global);
+        let chat_next = self.next_chat.get(&chat_id).copied().unwrap_or(now);
+        let slot = global.max(chat_next);
+
+        self.next_global = global + GLOBAL_INTERVAL;
+        self.next_chat
+            .insert(chat_id, slot + Self::chat_interval(chat_id));
+        slot
+    }
+
+    /// Telegram asked to wait, nothing is sent to the chat until `until`.
+    pub fn pause(&mut self, chat_id: i64, until: Instant) {
+        let next = self.next_chat.entry(chat_id).or_insert(until);
+        *next = (*next).max(until);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_respect_chat_and_global_limits() {
+        let mut limiter = Limiter::new();
+        let now = Instant::now();
+
+        let first = limiter.reserve(1, now);
+        let other = limiter.reserve(2, now);
+        let second = limiter.reserve(1, now);
+        let group = limiter.reserve(-1, now);
+        let group_second = limiter.reserve(-1, now);
+
+        assert_eq!(first, now);
+        assert_eq!(other, now + GLOBAL_INTERVAL);
+        assert_eq!(second, now + PRIVATE_INTERVAL);
+        assert!(group < second);
+        assert_eq!(group_second, group + GROUP_INTERVAL);
+
+        limiter.pause(2, now + Duration::from_secs(30));
+        assert_eq!(limiter.reserve(2, now), now + Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,367 @@
+mod limiter;
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use futures::future::join_all;
+use teloxide::types::{ChatId, FileId, InputFile, ParseMode};
+use teloxide::{prelude::*, ApiError, RequestError};
+use tokio::sync::{mpsc, oneshot, Mutex, Semaphore};
+use tokio::time::{sleep_until, Instant};
+use tokio_util::task::TaskTracker;
+
+use crate::database::{Database, Pool, Topic};
+use crate::metrics;
+
+use self::limiter::Limiter;
+
+const QUEUE_SIZE: usize = 1024;
+/// Sends waiting for their slot or in progress, the rest waits in the queue.
+const MAX_IN_FLIGHT: usize = 64;
+const MAX_ATTEMPTS: i32 = 3;
+const NETWORK_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub enum Content {
+    Text {
+        text: String,
+    },
+    Photo {
+        file_id: FileId,
+        caption: Option<String>,
+    },
+}
+
+/// Subscriptions a message is sent for.
+#[derive(Debug, Clone, Copy)]
+pub enum Subscriptions {
+    Topic(Topic),
+    /// Every topic of the chat, e.g. a campaign to `all`.
+    All,
+}
+
+/// Outbound message. Chats which can't be reached anymore lose their `subscriptions`.
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub chat_id: i64,
+    pub content: Content,
+    pub parse_mode: Option<ParseMode>,
+    pub subscriptions: Option<Subscriptions>,
+    /// What is delivered, goes to the delivery log.
+    pub kind: &'static str,
+}
+
+impl Delivery {
+    pub fn text(chat_id: i64, text: impl Into<String>) -> Self {
+        Self {
+            chat_id,
+            content: Content::Text { text: text.into() },
+            parse_mode: None,
+            subscriptions: None,
+            kind: "message",
+        }
+    }
+
+    pub fn photo(chat_id: i64, file_id: FileId, caption: Option<String>) -> Self {
+        Self {
+            chat_id,
+            content: Content::Photo { file_id, caption },
+            parse_mode: None,
+            subscriptions: None,
+            kind: "message",
+        }
+    }
+
+    pub fn parse_mode(mut self, parse_mode: ParseMode) -> Self {
+        self.parse_mode = Some(parse_mode);
+        self
+    }
+
+    pub fn topic(mut self, topic: Topic) -> Self {
+        self.subscriptions = Some(Subscriptions::Topic(topic));
+        self
+    }
+
+    pub fn all_topics(mut self) -> Self {
+        self.subscriptions = Some(Subscriptions::All);
+        self
+    }
+
+    pub fn kind(mut self, kind: &'static str) -> Self {
+        self.kind = kind;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "delivery_status", rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Sent,
+    Migrated,
+    Blocked,
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Migrated => "migrated",
+            DeliveryStatus::Blocked => "blocked",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Sent,
+    /// The group became a supergroup, the message went to the new chat.
+    Migrated(i64),
+    /// The bot was blocked or the chat is gone.
+    Blocked(String),
+    Failed(String),
+}
+
+impl Outcome {
+    pub fn is_sent(&self) -> bool {
+        matches!(self, Outcome::Sent | Outcome::Migrated(_))
+    }
+
+    pub fn status(&self) -> DeliveryStatus {
+        match self {
+            Outcome::Sent => DeliveryStatus::Sent,
+            Outcome::Migrated(_) => DeliveryStatus::Migrated,
+            Outcome::Blocked(_) => DeliveryStatus::Blocked,
+            Outcome::Failed(_) => DeliveryStatus::Failed,
+        }
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            Outcome::Blocked(e) | Outcome::Failed(e) => Some(e),
+            Outcome::Sent | Outcome::Migrated(_) => None,
+        }
+    }
+}
+
+struct Envelope {
+    delivery: Delivery,
+    reply: Option<oneshot::Sender<Outcome>>,
+}
+
+/// Queue of outbound messages, they are sent within Telegram limits by a background task.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    tx: mpsc::Sender<Envelope>,
+    sends: TaskTracker,
+}
+
+impl Outbox {
+    pub fn new(bot: Bot, pool: Pool) -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        let sends = TaskTracker::new();
+        tokio::spawn(Self::dispatch(bot, pool, rx, sends.clone()));
+        Self { tx, sends }
+    }
+
+    /// Waits for the messages which are being sent, called on shutdown.
+    pub async fn drain(&self) {
+        self.sends.close();
+        self.sends.wait().await;
+    }
+
+    /// Runs a long send, like a broadcast, in the background, `drain` waits for it too.
+    pub fn spawn(&self, task: impl std::future::Future<Output = ()> + Send + 'static) {
+        self.sends.spawn(task);
+    }
+
+    /// Queues the message without waiting for it to be sent.
+    pub async fn enqueue(&self, delivery: Delivery) -> Result<()> {
+        self.tx
+            .send(Envelope {
+                delivery,
+                reply: None,
+            })
+            .await
+            .map_err(|_| anyhow!("Delivery queue is closed"))
+    }
+
+    /// Queues the message and waits until it is sent or given up.
+    pub async fn deliver(&self, delivery: Delivery) -> Result<Outcome> {
+        let (reply, outcome) = oneshot::channel();
+        self.tx
+            .send(Envelope {
+                delivery,
+                reply: Some(reply),
+            })
+            .await
+            .map_err(|_| anyhow!("Delivery queue is closed"))?;
+        Ok(outcome.await?)
+    }
+
+    /// Fan-out, outcomes are in the order of `deliveries`.
+    pub async fn deliver_all(&self, deliveries: Vec<Delivery>) -> Result<Vec<Outcome>> {
+        join_all(
+            deliveries
+                .into_iter()
+                .map(|delivery| self.deliver(delivery)),
+        )
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    async fn dispatch(bot: Bot, pool: Pool, mut rx: mpsc::Receiver<Envelope>, sends: TaskTracker) {
+        let limiter = Arc::new(Mutex::new(Limiter::new()));
+        let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT));
+
+        // The queue is only read when a send is finished, so producers wait once it is full
+        while let Ok(permit) = in_flight.clone().acquire_owned().await {
+            let Some(envelope) = rx.recv().await else {
+                break;
+            };
+            let slot = limiter
+                .lock()
+                .await
+                .reserve(envelope.delivery.chat_id, Instant::now());
+            let (bot, pool, limiter) = (bot.clone(), pool.clone(), limiter.clone());
+
+            sends.spawn(async move {
+                let _permit = permit;
+                sleep_until(slot).await;
+                let (outcome, attempts) =
+                    Self::send(&bot, &pool, &limiter, &envelope.delivery).await;
+                Self::record(&pool, &envelope.delivery, &outcome, attempts).await;
+                if let Some(reply) = envelope.reply {
+                    reply.send(outcome).ok();
+                }
+            });
+        }
+    }
+
+    async fn send(
+        bot: &Bot,
+        pool: &Pool,
+        limiter: &Mutex<Limiter>,
+        delivery: &Delivery,
+    ) -> (Outcome, i32) {
+        let mut chat_id = delivery.chat_id;
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+            let error = match Self::request(bot, ChatId(chat_id), delivery).await {
+                Ok(()) if chat_id == delivery.chat_id => return (Outcome::Sent, attempts),
+                Ok(()) => return (Outcome::Migrated(chat_id), attempts),
+                Err(e) => e,
+            };
+
+            let wait = match error {
+                RequestError::RetryAfter(seconds) => {
+                    tracing::warn!("Flood control for chat {}, waiting {}", chat_id, seconds);
+                    let until = Instant::now() + seconds.duration();
+                    limiter.lock().await.pause(chat_id, until);
+                    until
+                }
+                RequestError::Network(_) => Instant::now() + NETWORK_RETRY_DELAY,
+                RequestError::MigrateToChatId(new_chat_id) => {
+                    tracing::warn!(
+                        "Chat {} was migrated to {}. Replacing",
+                        chat_id,
+                        new_chat_id
+                    );
+                    if let Err(e) = Self::migrate(pool, chat_id, new_chat_id.0).await {
+                        tracing::error!("Failed to migrate chat {}: {}", chat_id, e);
+                    }
+                    chat_id = new_chat_id.0;
+                    Instant::now()
+                }
+                RequestError::Api(
+                    ApiError::BotBlocked | ApiError::ChatNotFound | ApiError::UserDeactivated,
+                ) => {
+                    tracing::warn!(
+                        "Chat {} can't be reached ({}). Removing from active chats",
+                        chat_id,
+                        error
+                    );
+                    if let Err(e) = Self::cleanup(pool, delivery, chat_id).await {
+                        tracing::error!("Failed to remove chat {}: {}", chat_id, e);
+                    }
+                    return (Outcome::Blocked(error.to_string()), attempts);
+                }
+                _ => {
+                    sentry::capture_error(&error);
+                    return (Outcome::Failed(error.to_string()), attempts);
+                }
+            };
+
+            if attempts >= MAX_ATTEMPTS {
+                sentry::capture_error(&error);
+                return (Outcome::Failed(error.to_string()), attempts);
+            }
+            let slot = limiter.lock().await.reserve(chat_id, wait);
+            sleep_until(slot).await;
+        }
+    }
+
+    async fn request(bot: &Bot, chat_id: ChatId, delivery: &Delivery) -> Result<(), RequestError> {
+        match delivery.content {
+            Content::Text { ref text } => {
+                let mut request = bot.send_message(chat_id, text);
+                request.parse_mode = delivery.parse_mode;
+                request.send().await?;
+            }
+            Content::Photo {
+                ref file_id,
+                ref caption,
+            } => {
+                let mut request = bot.send_photo(chat_id, InputFile::file_id(file_id.clone()));
+                request.caption = caption.clone();
+                request.parse_mode = delivery.parse_mode;
+                request.send().await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn cleanup(pool: &Pool, delivery: &Delivery, chat_id: i64) -> Result<()> {
+        let Some(subscriptions) = delivery.subscriptions else {
+            return Ok(());
+        };
+        let db = Database::new(pool.clone()).await?;
+        match subscriptions {
+            Subscriptions::Topic(topic) => {
+                db.unsubscribe(chat_id, topic).await?;
+            }
+            Subscriptions::All => {
+                db.unsubscribe_all(chat_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn migrate(pool: &Pool, from: i64, to: i64) -> Result<()> {
+        Database::new(pool.clone())
+            .await?
+            .migrate_chat(from, to)
+            .await
+    }
+
+    /// The log is best effort, a failed insert doesn't affect the delivery.
+    async fn record(pool: &Pool, delivery: &Delivery, outcome: &Outcome, attempts: i32) {
+        metrics::MESSAGES
+            .with_label_values(&[delivery.kind, outcome.status().as_str()])
+            .inc();
+        let result = match Database::new(pool.clone()).await {
+            Ok(db) => {
+                db.add_delivery_log(delivery.chat_id, delivery.kind, outcome, attempts)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to record delivery to {}: {}", delivery.chat_id, e);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+use crate::database::Topic;
+
+/// Chats and users mapping, moved between databases by `export` and `import`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Dump {
+    pub chats: Vec<ChatDump>,
+    pub mapping: Vec<MappingEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatDump {
+    pub chat_id: i64,
+    pub timezone: String,
+    pub toad_time: NaiveTime,
+    pub topics: Vec<Topic>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MappingEntry {
+    pub user_id: i64,
+    pub username: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_round_trips() {
+        let dump = Dump {
+            chats: vec![ChatDump {
+                chat_id: -100,
+                timezone: "Europe/Moscow".to_owned(),
+                toad_time: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                topics: vec![Topic::Wednesday, Topic::Crypto],
+            }],
+            mapping: vec![MappingEntry {
+                user_id: 1,
+                username: "toad".to_owned(),
+            }],
+        };
+        let json = serde_json::to_string(&dump).unwrap();
+        assert!(json.contains(r#""topics":["wednesday","crypto"]"#));
+        assert!(json.contains(r#""toad_time":"09:00:00""#));
+        assert_eq!(serde_json::from_str::<Dump>(&json).unwrap(), dump);
+    }
+}
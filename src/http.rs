@@ -0,0 +1,85 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use tokio_util::sync::CancellationToken;
+
+use crate::cache::Cache;
+use crate::database::Pool;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Clone)]
+struct Probes {
+    pool: Pool,
+    cache: Cache,
+}
+
+/// Serves `/healthz`, `/readyz` and `/metrics` until the shutdown.
+pub async fn serve(
+    addr: SocketAddr,
+    pool: Pool,
+    cache: Cache,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .with_state(Probes { pool, cache });
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("HTTP server listening on {}", addr);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await?;
+    Ok(())
+}
+
+/// The process is alive and serving requests.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Postgres and the cache answer, so the bot can do its work. Redis being down
+/// doesn't make the bot unready while the cache falls back to memory.
+async fn readyz(State(probes): State<Probes>) -> (StatusCode, String) {
+    let db = probe(async {
+        sqlx::query("SELECT 1").execute(&probes.pool).await?;
+        Ok(())
+    })
+    .await;
+    let cache = probe(probes.cache.ping()).await;
+
+    let status = if db.is_ok() && cache.is_ok() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = format!(
+        "database: {}\ncache: {}\n",
+        db.err().unwrap_or("ok".to_owned()),
+        cache.map_or_else(|e| e, str::to_owned)
+    );
+    (status, body)
+}
+
+async fn probe<T>(check: impl Future<Output = Result<T>>) -> Result<T, String> {
+    match tokio::time::timeout(PROBE_TIMEOUT, check).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => {
+            tracing::error!("Readiness probe failed: {}", e);
+            Err(e.to_string())
+        }
+        Err(_) => Err("timed out".to_owned()),
+    }
+}
+
+async fn metrics() -> (StatusCode, String) {
+    match crate::metrics::encode() {
+        Ok(text) => (StatusCode::OK, text),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
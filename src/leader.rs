@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use sqlx::pool::PoolConnection;
+use sqlx::{Connection, Postgres};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::database::Pool;
+use crate::metrics;
+
+/// Key of the advisory lock on scheduled jobs, the same for every replica.
+const LOCK_KEY: i64 = 0x7765_646e_6573_6461; // "wednesda"
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Lease on scheduled jobs, held by one replica at a time.
+///
+/// It is a session advisory lock in Postgres, which every replica needs anyway. The lock
+/// lives as long as the connection which took it: when the leader stops or loses the
+/// database, another replica takes over on its next check.
+pub struct Leader {
+    pool: Pool,
+    /// Connection holding the lock, it is taken out of the pool.
+    session: Option<PoolConnection<Postgres>>,
+    leading: watch::Sender<bool>,
+}
+
+impl Leader {
+    pub fn new(pool: Pool) -> Self {
+        Self {
+            pool,
+            session: None,
+            leading: watch::Sender::new(false),
+        }
+    }
+
+    /// Follows whether this replica holds the lease.
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.leading.subscribe()
+    }
+
+    /// Keeps competing for the lease until the shutdown, it is released by `release`.
+    pub fn elect(mut self, shutdown: CancellationToken) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            loop {
+                let leading = match self.session {
+                    Some(ref mut session) => match session.ping().await {
+                        Ok(()) => true,
+                        Err(e) => {
+                            // The lock went away with the session
+                            tracing::error!("Lost the session holding the scheduler lease: {}", e);
+                            self.session = None;
+                            false
+                        }
+                    },
+                    None => match self.acquire().await {
+                        Ok(leading) => leading,
+                        Err(e) => {
+                            tracing::error!("Failed to take the scheduler lease: {}", e);
+                            false
+                        }
+                    },
+                };
+                self.set_leading(leading);
+
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+                }
+            }
+            self
+        })
+    }
+
+    /// Lets another replica take over right away.
+    pub async fn release(mut self) {
+        self.leading.send_replace(false);
+        metrics::LEADER.set(0);
+        let Some(mut session) = self.session.take() else {
+            return;
+        };
+        let released = sqlx::query_scalar!("SELECT pg_advisory_unlock($1)", LOCK_KEY)
+            .fetch_one(&mut *session)
+            .await;
+        match released {
+            Ok(_) => tracing::info!("Scheduler lease is released"),
+            Err(e) => tracing::error!("Failed to release the scheduler lease: {}", e),
+        }
+        // Closing the session releases the lock in any case
+        if let Err(e) = session.detach().close().await {
+            tracing::warn!("Failed to close the scheduler lease session: {}", e);
+        }
+    }
+
+    async fn acquire(&mut self) -> Result<bool> {
+        let mut session = self.pool.acquire().await?;
+        let taken = sqlx::query_scalar!("SELECT pg_try_advisory_lock($1)", LOCK_KEY)
+            .fetch_one(&mut *session)
+            .await?
+            .unwrap_or(false);
+        if taken {
+            self.session = Some(session);
+        }
+        Ok(taken)
+    }
+
+    fn set_leading(&self, leading: bool) {
+        let changed = self.leading.send_if_modified(|current| {
+            let changed = *current != leading;
+            *current = leading;
+            changed
+        });
+        if changed {
+            if leading {
+                tracing::info!("Scheduler lease is taken");
+            } else {
+                tracing::warn!("Scheduler lease is lost");
+            }
+        }
+        metrics::LEADER.set(leading as i64);
+    }
+}
@@ -1,10 +1,26 @@
+mod admins;
+mod alerts;
 mod bot;
 mod cache;
+mod campaigns;
+mod chart;
+mod cli;
+mod coins;
 mod config;
 mod database;
+mod delivery;
+mod dump;
+mod http;
+mod leader;
+mod members;
+mod metrics;
+mod period;
 mod rates;
 mod scheduler;
+mod shutdown;
+mod tickets;
 mod toads;
+mod webhook;
 
 use std::sync::{Arc, RwLock};
 
@@ -12,18 +28,16 @@ use crate::{bot::Gauss, database::Database};
 
 use anyhow::Result;
 use build_time::build_time_utc;
+use clap::Parser;
 use teloxide::prelude::*;
+use tokio_util::sync::CancellationToken;
 use tracing::level_filters::LevelFilter;
-use tracing_subscriber::{fmt, prelude::*};
+use tracing_subscriber::{fmt, fmt::writer::BoxMakeWriter, prelude::*};
 
-async fn try_main(cfg: config::Cfg) -> Result<()> {
-    let db_path = cfg.db;
-    let cache_path = cfg.cache;
+async fn try_main(cfg: Arc<config::Cfg>) -> Result<()> {
+    let pool = sqlx::PgPool::connect(&cfg.db).await?;
 
-    let pool = sqlx::PgPool::connect(&db_path).await?;
-
-    let cache_manager = bb8_redis::RedisConnectionManager::new(format!("redis://{}", cache_path))?;
-    let cache_pool = bb8::Pool::builder().build(cache_manager).await?;
+    let cache = cache::Cache::open(&cfg)?;
 
     tracing::debug!("testing database connection...");
     {
@@ -35,63 +49,42 @@ async fn try_main(cfg: config::Cfg) -> Result<()> {
     let bot = teloxide::Bot::new(token);
     let admin_user_id = cfg.admin_user_id;
 
-    let _scheduler = scheduler::Scheduler::new(bot.clone(), pool.clone(), cache_pool.clone());
+    let outbox = delivery::Outbox::new(bot.clone(), pool.clone());
+
+    let shutdown = CancellationToken::new();
+    shutdown::listen(shutdown.clone());
+
+    let scheduler = cfg.scheduler.enabled.then(|| {
+        scheduler::Scheduler::new(
+            outbox.clone(),
+            pool.clone(),
+            cache.clone(),
+            cfg.coins.clone(),
+            std::time::Duration::from_secs(cfg.scheduler.tick_seconds),
+            shutdown.clone(),
+        )
+    });
 
-    // Heartbeat for healthcheck
-    let pool_for_health = pool.clone();
-    let cache_pool_for_health = cache_pool.clone();
+    let http_addr = cfg.http.addr;
+    let http_pool = pool.clone();
+    let http_cache = cache.clone();
+    let http_shutdown = shutdown.clone();
     tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
-        let healthcheck_path = "/tmp/wednesday_health";
-
-        loop {
-            interval.tick().await;
-
-            // Check PostgreSQL
-            let db_ok = sqlx::query("SELECT 1").execute(&pool_for_health).await.is_ok();
-            
-            // Check Redis
-            let mut redis_conn = cache_pool_for_health.get().await;
-            let cache_ok = match redis_conn {
-                Ok(mut conn) => {
-                    // Using bb8-redis, we check connection health via a simple PING if possible 
-                    // or just by the fact that we got a connection from the pool.
-                    // For more robustness, we could execute a command through the connection.
-                    true // Simplified for now as getting a connection is a good proxy
-                },
-                Err(_) => false,
-            };
-
-            if db_ok && cache_ok {
-                let timestamp = chrono::Utc::now().to_rfc3339();
-                if let Err(e) = tokio::fs::write(healthcheck_path, &timestamp).await {
-                    tracing::error!("Failed to write healthcheck file: {}", e);
-                }
-            } else {
-                if !db_ok {
-                    tracing::error!("Healthcheck failed: Database unreachable");
-                }
-                if !cache_ok {
-                    tracing::error!("Healthcheck failed: Cache unreachable");
-                }
-
-                if let Err(e) = tokio::fs::remove_file(healthcheck_path).await {
-                    // If file doesn't exist, ignore the error
-                    if e.kind() != std::io::ErrorKind::NotFound {
-                        tracing::error!("Failed to remove healthcheck file: {}", e);
-                    }
-                }
-            }
+        if let Err(e) = http::serve(http_addr, http_pool, http_cache, http_shutdown).await {
+            tracing::error!("HTTP server stopped: {}", e);
         }
     });
 
-    Dispatcher::builder(bot, crate::bot::get_handler())
+    let mut dispatcher = Dispatcher::builder(bot.clone(), crate::bot::get_handler())
         .dependencies(dptree::deps![
             pool.clone(),
-            cache_pool.clone(),
+            cache.clone(),
             cfg.bot_name.clone(),
             Arc::new(RwLock::new(Gauss::new(17., 4.))),
-            admin_user_id
+            admin_user_id,
+            cfg.coins.clone(),
+            outbox.clone(),
+            cfg.clone()
         ])
         .default_handler(|upd| async move {
             tracing::warn!("Unhandled update: {:?}", upd);
@@ -100,10 +93,37 @@ async fn try_main(cfg: config::Cfg) -> Result<()> {
         .error_handler(LoggingErrorHandler::with_custom_text(
             "An error has occurred in the dispatcher",
         ))
-        .enable_ctrlc_handler()
-        .build()
-        .dispatch()
-        .await;
+        .build();
+
+    let dispatcher_shutdown = dispatcher.shutdown_token();
+    let stop_dispatcher = shutdown.clone();
+    tokio::spawn(async move {
+        stop_dispatcher.cancelled().await;
+        match dispatcher_shutdown.shutdown() {
+            Ok(stopped) => stopped.await,
+            Err(e) => tracing::warn!("Dispatcher is not running: {}", e),
+        }
+    });
+
+    match cfg.webhook.clone() {
+        None => dispatcher.dispatch().await,
+        Some(webhook) => {
+            let listener = webhook::listener(&bot, webhook).await?;
+            dispatcher
+                .dispatch_with_listener(
+                    listener,
+                    LoggingErrorHandler::with_custom_text("An error from the webhook listener"),
+                )
+                .await
+        }
+    }
+
+    // The dispatcher may also stop on its own, everything else follows it
+    shutdown.cancel();
+    shutdown::drain(scheduler, &outbox).await;
+    pool.close().await;
+    drop(cache);
+    tracing::info!("Shutdown is complete");
 
     Ok(())
 }
@@ -116,22 +136,45 @@ async fn main() {
         std::process::exit(1);
     }));
 
-    let cfg = config::Cfg::new().expect("Could not initialize config");
+    let cli = cli::Cli::parse();
+    let command = cli.command.unwrap_or(cli::Command::Run);
+
+    let cfg = match config::Cfg::new() {
+        Ok(cfg) => Arc::new(cfg),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if cli.check_config {
+        println!("Config is valid");
+        return;
+    }
 
     let mut options = sentry::ClientOptions::new();
     options.release = Some(std::borrow::Cow::from(build_time_utc!()));
     options.attach_stacktrace = true;
-    options.traces_sample_rate = cfg.traces_sample_rate;
+    options.traces_sample_rate = cfg
+        .sentry
+        .as_ref()
+        .map_or(0., |sentry| sentry.traces_sample_rate);
     #[cfg(debug_assertions)]
     {
         options.debug = true;
     }
     options.debug = true;
 
-    let _guard = sentry::init((cfg.sentry_url.clone(), options));
+    let sentry_url = cfg.sentry.as_ref().map(|sentry| sentry.url.as_str());
+    let _guard = sentry::init((sentry_url, options));
 
+    // Ops commands may print to stdout, their logs go to stderr
+    let writer = match command {
+        cli::Command::Run => BoxMakeWriter::new(std::io::stdout),
+        _ => BoxMakeWriter::new(std::io::stderr),
+    };
     let fmt_layer = fmt::layer()
         .with_target(false)
+        .with_writer(writer)
         .with_filter(LevelFilter::INFO);
 
     let sentry_layer = sentry::integrations::tracing::layer().with_filter(LevelFilter::INFO);
@@ -141,7 +184,11 @@ async fn main() {
         .with(fmt_layer)
         .init();
 
-    if let Err(ref e) = try_main(cfg).await {
+    let result = match command {
+        cli::Command::Run => try_main(cfg).await,
+        command => cli::run(command, &cfg).await,
+    };
+    if let Err(ref e) = result {
         sentry::integrations::anyhow::capture_anyhow(e);
 
         eprintln!("Program finished with error: {}", e);
@@ -0,0 +1,62 @@
+use anyhow::Result;
+
+use crate::bot::escape_text;
+
+/// Telegram keeps at most 100 entities in a message, leave some room for the rest of the text.
+pub const MENTIONS_PER_MESSAGE: usize = 50;
+
+/// Known member of a group chat, `name` comes from the users mapping.
+#[derive(Debug, Clone)]
+pub struct ChatMember {
+    pub user_id: i64,
+    pub name: Option<String>,
+}
+
+impl ChatMember {
+    /// MarkdownV2 mention which works for users without a username as well.
+    pub fn mention(&self) -> Result<String> {
+        let name = self.name.clone().unwrap_or(self.user_id.to_string());
+        Ok(format!(
+            "[{}](tg://user?id={})",
+            escape_text(name)?,
+            self.user_id
+        ))
+    }
+}
+
+/// Splits mentions of the members into MarkdownV2 messages.
+pub fn mention_messages(members: &[ChatMember], per_message: usize) -> Result<Vec<String>> {
+    members
+        .chunks(per_message.max(1))
+        .map(|chunk| {
+            Ok(chunk
+                .iter()
+                .map(ChatMember::mention)
+                .collect::<Result<Vec<String>>>()?
+                .join(" "))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mentions_are_split_into_messages() {
+        let members: Vec<ChatMember> = (1..=5)
+            .map(|user_id| ChatMember {
+                user_id,
+                name: (user_id != 5).then(|| format!("user_{}", user_id)),
+            })
+            .collect();
+
+        let messages = mention_messages(&members, 2).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0],
+            "[user\\_1](tg://user?id=1) [user\\_2](tg://user?id=2)"
+        );
+        assert_eq!(messages[2], "[5](tg://user?id=5)");
+    }
+}
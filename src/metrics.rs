@@ -0,0 +1,92 @@
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use anyhow::Result;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, Encoder, HistogramVec,
+    IntCounterVec, IntGauge, TextEncoder,
+};
+
+pub static COMMANDS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "wednesday_commands_total",
+        "Bot commands handled",
+        &["command"]
+    )
+    .expect("commands metric is registered once")
+});
+
+pub static TASK_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "wednesday_task_duration_seconds",
+        "Duration of scheduler tasks by outcome",
+        &["task", "outcome"],
+        vec![0.1, 0.5, 1., 5., 15., 60., 300., 900.]
+    )
+    .expect("task metric is registered once")
+});
+
+pub static RATE_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "wednesday_rate_request_duration_seconds",
+        "Latency of rate API requests per source",
+        &["source", "outcome"]
+    )
+    .expect("rate metric is registered once")
+});
+
+pub static MESSAGES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "wednesday_messages_total",
+        "Outbound messages by kind and delivery status",
+        &["kind", "status"]
+    )
+    .expect("messages metric is registered once")
+});
+
+pub static LEADER: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "wednesday_scheduler_leader",
+        "Whether this replica holds the scheduler lease"
+    )
+    .expect("leader metric is registered once")
+});
+
+pub fn outcome<T, E>(result: &std::result::Result<T, E>) -> &'static str {
+    if result.is_ok() {
+        "ok"
+    } else {
+        "error"
+    }
+}
+
+/// Observes the time since `started` in seconds.
+pub fn observe(histogram: &HistogramVec, labels: &[&str], started: Instant) {
+    histogram
+        .with_label_values(labels)
+        .observe(started.elapsed().as_secs_f64());
+}
+
+/// Metrics in the Prometheus text format.
+pub fn encode() -> Result<String> {
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_registered_metrics() {
+        COMMANDS.with_label_values(&["rate"]).inc();
+        observe(&TASK_DURATION, &["wednesday", "ok"], Instant::now());
+
+        let text = encode().unwrap();
+        assert!(text.contains("wednesday_commands_total{command=\"rate\"}"));
+        assert!(text.contains(
+            "wednesday_task_duration_seconds_count{outcome=\"ok\",task=\"wednesday\"} 1"
+        ));
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::{anyhow, Result};
+use chrono::Duration;
+
+/// Parses periods like `30m`, `1h` or `7d`.
+pub fn parse_period(text: &str) -> Result<Duration> {
+    let (value, unit) = text.split_at(text.trim_end_matches(char::is_alphabetic).len());
+    let value = value
+        .parse::<i64>()
+        .map_err(|_| anyhow!("Could not parse period `{}`", text))?;
+    let period = match unit {
+        "m" => Duration::try_minutes(value),
+        "h" => Duration::try_hours(value),
+        "d" => Duration::try_days(value),
+        _ => return Err(anyhow!("Unknown period unit in `{}`", text)),
+    }
+    .ok_or(anyhow!("Period `{}` is too long", text))?;
+    if period <= Duration::zero() {
+        return Err(anyhow!("Period should be positive"));
+    }
+    Ok(period)
+}
+
+pub fn format_period(period: Duration) -> String {
+    let secs = period.num_seconds();
+    if secs % 86400 == 0 {
+        format!("{}d", secs / 86400)
+    } else if secs % 3600 == 0 {
+        format!("{}h", secs / 3600)
+    } else {
+        format!("{}m", secs / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format_period() {
+        assert_eq!(parse_period("30m").unwrap(), Duration::minutes(30));
+        assert_eq!(parse_period("24h").unwrap(), Duration::days(1));
+        assert_eq!(format_period(parse_period("24h").unwrap()), "1d");
+        assert_eq!(format_period(parse_period("90m").unwrap()), "90m");
+        assert!(parse_period("0d").is_err());
+        assert!(parse_period("1w").is_err());
+        assert!(parse_period("d").is_err());
+    }
+}
@@ -1,5 +1,9 @@
+use crate::chart::{Candle, Resolution};
+use crate::coins::Coin;
+use crate::metrics;
 use crate::retry;
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use serde::Deserialize;
 use serde_this_or_that::as_f64;
 use tracing::instrument;
@@ -49,7 +53,7 @@ async fn request_rate_from_binance(coin: &str) -> Result<f64> {
         let rate = price
             .parse::<f64>()
             .map_err(|_| anyhow!("Failed to parse `price` value as a f64: {}", price))?;
-        return Ok(rate);
+        Ok(rate)
     }
 
     let rate = retry! { request(&url).await }?;
@@ -204,96 +208,260 @@ async fn request_rate_from_binance_with_24hr_change(coin: &str) -> Result<(f64,
 }
 
 #[instrument]
-pub async fn get_btc_rate() -> Result<f64> {
-    request_rate_from_binance("BTC").await
-}
+pub async fn request_klines_from_binance(
+    coin: &str,
+    resolution: Resolution,
+    limit: usize,
+) -> Result<Vec<Candle>> {
+    let url = format!(
+        "https://api.binance.com/api/v3/klines?symbol={}USDT&interval={}&limit={}",
+        coin,
+        resolution.binance_interval(),
+        limit.clamp(1, 1000)
+    );
 
-#[instrument]
-pub async fn get_btc_rate_with_24hr_change() -> Result<(f64, f64)> {
-    request_rate_from_binance_with_24hr_change("BTC").await
-}
+    async fn request(url: &str) -> Result<Vec<Candle>> {
+        let data: Vec<Vec<serde_json::Value>> = reqwest::get(url).await?.json().await?;
+        data.iter()
+            .map(|kline| {
+                let value = |i: usize| -> Result<f64> {
+                    kline
+                        .get(i)
+                        .and_then(|v| v.as_str())
+                        .and_then(|v| v.parse::<f64>().ok())
+                        .ok_or(anyhow!("Unexpected format of kline: {:?}", kline))
+                };
+                let bucket = kline
+                    .first()
+                    .and_then(|v| v.as_i64())
+                    .and_then(chrono::DateTime::from_timestamp_millis)
+                    .ok_or(anyhow!("Unexpected open time of kline: {:?}", kline))?;
+                Ok(Candle {
+                    bucket,
+                    open: value(1)?,
+                    high: value(2)?,
+                    low: value(3)?,
+                    close: value(4)?,
+                })
+            })
+            .collect()
+    }
 
-#[instrument]
-pub async fn get_eth_rate() -> Result<f64> {
-    request_rate_from_binance("ETH").await
+    let candles = retry! { request(&url).await }?;
+    Ok(candles)
 }
 
-#[instrument]
-pub async fn get_eth_rate_with_24hr_change() -> Result<(f64, f64)> {
-    request_rate_from_binance_with_24hr_change("ETH").await
+/// A backend able to report the USD price of a single asset.
+#[async_trait]
+pub trait RateSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn rate(&self) -> Result<f64>;
+
+    /// Price together with its 24h change in percents, if the backend knows it.
+    async fn rate_with_24hr_change(&self) -> Result<(f64, Option<f64>)> {
+        Ok((self.rate().await?, None))
+    }
 }
 
-#[instrument]
-pub async fn get_ltc_rate() -> Result<f64> {
-    request_rate_from_binance("LTC").await
+#[derive(Debug, Clone)]
+pub struct Binance {
+    symbol: String,
 }
 
-#[instrument]
-pub async fn get_sol_rate() -> Result<f64> {
-    request_rate_from_binance("SOL").await
+impl Binance {
+    pub fn new(symbol: impl Into<String>) -> Self {
+        Self {
+            symbol: symbol.into(),
+        }
+    }
 }
 
-#[instrument]
-pub async fn get_etc_rate() -> Result<f64> {
-    request_rate_from_binance("ETC").await
+#[async_trait]
+impl RateSource for Binance {
+    fn name(&self) -> &'static str {
+        "Binance"
+    }
+
+    async fn rate(&self) -> Result<f64> {
+        request_rate_from_binance(&self.symbol).await
+    }
+
+    async fn rate_with_24hr_change(&self) -> Result<(f64, Option<f64>)> {
+        let (rate, change) = request_rate_from_binance_with_24hr_change(&self.symbol).await?;
+        Ok((rate, Some(change)))
+    }
 }
 
-#[instrument]
-pub async fn get_bnb_rate() -> Result<f64> {
-    request_rate_from_binance("BNB").await
+#[derive(Debug, Clone)]
+pub struct CoinGecko {
+    id: String,
 }
 
-#[instrument]
-pub async fn get_ada_rate() -> Result<f64> {
-    request_rate_from_coingecko("cardano").await
+impl CoinGecko {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self { id: id.into() }
+    }
 }
 
-#[instrument]
-pub async fn get_zee_rate() -> Result<f64> {
-    request_rate_from_coingecko("zeroswap").await
+#[async_trait]
+impl RateSource for CoinGecko {
+    fn name(&self) -> &'static str {
+        "CoinGecko"
+    }
+
+    async fn rate(&self) -> Result<f64> {
+        request_rate_from_coingecko(&self.id).await
+    }
+
+    async fn rate_with_24hr_change(&self) -> Result<(f64, Option<f64>)> {
+        let (rate, change) = request_rate_from_coingecko_with_24hr_change(&self.id).await?;
+        Ok((rate, Some(change)))
+    }
 }
 
-#[instrument]
-pub async fn get_not_rate() -> Result<f64> {
-    request_rate_from_binance("NOT").await
+#[derive(Debug, Clone)]
+pub struct CurrencyApi {
+    from: String,
+    to: String,
 }
 
-#[instrument]
-pub async fn get_ton_rate() -> Result<f64> {
-    request_rate_from_coingecko("the-open-network").await
+impl CurrencyApi {
+    pub fn new(from: impl Into<String>, to: impl Into<String>) -> Self {
+        Self {
+            from: from.into(),
+            to: to.into(),
+        }
+    }
 }
 
-#[instrument]
-pub async fn get_zee_rate_with_24hr_change() -> Result<(f64, f64)> {
-    request_rate_from_coingecko_with_24hr_change("zeroswap").await
+#[async_trait]
+impl RateSource for CurrencyApi {
+    fn name(&self) -> &'static str {
+        "currency-api"
+    }
+
+    async fn rate(&self) -> Result<f64> {
+        request_non_coin_rate(&self.from, &self.to).await
+    }
 }
 
-#[instrument]
-pub async fn get_sol_rate_with_24hr_change() -> Result<(f64, f64)> {
-    request_rate_from_coingecko_with_24hr_change("solana").await
+#[derive(Debug, Clone, Copy)]
+pub struct Rate {
+    pub price: f64,
+    pub change: Option<f64>,
+    pub source: &'static str,
 }
 
-#[instrument]
-pub async fn get_bnb_rate_with_24hr_change() -> Result<(f64, f64)> {
-    request_rate_from_binance_with_24hr_change("BNB").await
+/// Asks the sources one by one and returns the first successful answer.
+#[instrument(skip(sources))]
+pub async fn fetch_rate(
+    coin: &str,
+    sources: &[Box<dyn RateSource>],
+    with_24hr_change: bool,
+) -> Result<Rate> {
+    let mut errors = vec![];
+
+    for source in sources {
+        let started = std::time::Instant::now();
+        let result = if with_24hr_change {
+            source.rate_with_24hr_change().await
+        } else {
+            source.rate().await.map(|rate| (rate, None))
+        };
+        metrics::observe(
+            &metrics::RATE_LATENCY,
+            &[source.name(), metrics::outcome(&result)],
+            started,
+        );
+
+        match result {
+            Ok((price, change)) => {
+                return Ok(Rate {
+                    price,
+                    change,
+                    source: source.name(),
+                })
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Source {} failed to provide {} rate: {}",
+                    source.name(),
+                    coin,
+                    e
+                );
+                errors.push(format!("{}: {}", source.name(), e));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        return Err(anyhow!("There are no rate sources for {}", coin));
+    }
+
+    Err(anyhow!(
+        "All sources failed to provide {} rate: {}",
+        coin,
+        errors.join("; ")
+    ))
 }
 
-#[instrument]
-pub async fn get_luna_rate_with_24hr_change() -> Result<(f64, f64)> {
-    request_rate_from_coingecko_with_24hr_change("terra-luna").await
+#[instrument(skip(coin), fields(coin = coin.ticker))]
+pub async fn get_coin_rate(coin: &Coin) -> Result<Rate> {
+    fetch_rate(&coin.ticker, &coin.sources(), false).await
 }
 
-#[instrument]
-pub async fn get_not_rate_with_24hr_change() -> Result<(f64, f64)> {
-    request_rate_from_coingecko_with_24hr_change("notcoin").await
+#[instrument(skip(coin), fields(coin = coin.ticker))]
+pub async fn get_coin_rate_with_24hr_change(coin: &Coin) -> Result<Rate> {
+    fetch_rate(&coin.ticker, &coin.sources(), true).await
 }
 
 #[instrument]
-pub async fn get_ton_rate_with_24hr_change() -> Result<(f64, f64)> {
-    request_rate_from_coingecko_with_24hr_change("the-open-network").await
+pub async fn get_usd_rate() -> Result<Rate> {
+    let sources: Vec<Box<dyn RateSource>> = vec![Box::new(CurrencyApi::new("usd", "rub"))];
+    fetch_rate("USD", &sources, false).await
 }
 
-#[instrument]
-pub async fn get_usd_rate() -> Result<f64> {
-    request_non_coin_rate("usd", "rub").await
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Failing;
+
+    #[async_trait]
+    impl RateSource for Failing {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn rate(&self) -> Result<f64> {
+            Err(anyhow!("rate limited"))
+        }
+    }
+
+    struct Fixed(f64);
+
+    #[async_trait]
+    impl RateSource for Fixed {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        async fn rate(&self) -> Result<f64> {
+            Ok(self.0)
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_rate_falls_back_to_next_source() {
+        let sources: Vec<Box<dyn RateSource>> =
+            vec![Box::new(Failing), Box::new(Fixed(42.)), Box::new(Fixed(7.))];
+        let rate = fetch_rate("BTC", &sources, true).await.unwrap();
+        assert_eq!(rate.price, 42.);
+        assert_eq!(rate.change, None);
+        assert_eq!(rate.source, "fixed");
+
+        let sources: Vec<Box<dyn RateSource>> = vec![Box::new(Failing)];
+        assert!(fetch_rate("BTC", &sources, false).await.is_err());
+    }
 }
@@ -0,0 +1,217 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+/// `all` doesn't replay more than this after a long downtime.
+const MAX_CATCH_UP_RUNS: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Job {
+    Wednesday,
+    Crypto,
+    RateCheck,
+    Alerts,
+    PriceHistory,
+    Heartbeat,
+}
+
+impl Job {
+    pub const ALL: [Job; 6] = [
+        Job::Wednesday,
+        Job::Crypto,
+        Job::RateCheck,
+        Job::Alerts,
+        Job::PriceHistory,
+        Job::Heartbeat,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Job::Wednesday => "wednesday",
+            Job::Crypto => "crypto",
+            Job::RateCheck => "rate_check",
+            Job::Alerts => "alerts",
+            Job::PriceHistory => "price_history",
+            Job::Heartbeat => "heartbeat",
+        }
+    }
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Job {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Job::ALL
+            .into_iter()
+            .find(|job| job.as_str() == s)
+            .ok_or(anyhow!("Unknown job `{}`", s))
+    }
+}
+
+/// What to do on startup with the runs missed while the bot was down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "catch_up", rename_all = "lowercase")]
+pub enum CatchUp {
+    Skip,
+    Once,
+    All,
+}
+
+impl CatchUp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CatchUp::Skip => "skip",
+            CatchUp::Once => "once",
+            CatchUp::All => "all",
+        }
+    }
+}
+
+impl fmt::Display for CatchUp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CatchUp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(CatchUp::Skip),
+            "once" => Ok(CatchUp::Once),
+            "all" => Ok(CatchUp::All),
+            _ => Err(anyhow!("Unknown catch-up `{}`, use skip, once or all", s)),
+        }
+    }
+}
+
+/// Row of the `schedules` table.
+#[derive(Debug, Clone)]
+pub struct ScheduleEntry {
+    pub job: String,
+    pub cron: String,
+    pub timezone: String,
+    pub enabled: bool,
+    pub catch_up: CatchUp,
+    pub last_run_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct JobSchedule {
+    pub job: Job,
+    pub schedule: cron::Schedule,
+    pub timezone: Tz,
+    pub enabled: bool,
+    pub catch_up: CatchUp,
+    pub last_run_at: Option<DateTime<Utc>>,
+}
+
+impl JobSchedule {
+    pub fn parse(job: &str, cron: &str, timezone: &str, enabled: bool) -> Result<Self> {
+        Ok(Self {
+            job: job.parse()?,
+            schedule: cron::Schedule::from_str(cron)
+                .map_err(|e| anyhow!("Invalid cron expression `{}`: {}", cron, e))?,
+            timezone: timezone
+                .parse()
+                .map_err(|e| anyhow!("Invalid timezone `{}`: {}", timezone, e))?,
+            enabled,
+            catch_up: CatchUp::Skip,
+            last_run_at: None,
+        })
+    }
+
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Tz>> {
+        self.schedule
+            .after(&after.with_timezone(&self.timezone))
+            .next()
+    }
+
+    /// Whether the job should have fired in `(from, to]`.
+    pub fn fires_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> bool {
+        self.next_after(from).is_some_and(|next| next <= to)
+    }
+
+    /// How many runs to make up for on taking the lead, the ones missed since the last run
+    /// are counted. Nothing is known about a job which has never run.
+    pub fn catch_up_runs(&self, now: DateTime<Utc>) -> usize {
+        let Some(last_run_at) = self.last_run_at else {
+            return 0;
+        };
+        let missed = self
+            .schedule
+            .after(&last_run_at.with_timezone(&self.timezone))
+            .take_while(|next| next.with_timezone(&Utc) <= now)
+            .take(MAX_CATCH_UP_RUNS)
+            .count();
+        match self.catch_up {
+            CatchUp::Skip => 0,
+            CatchUp::Once => missed.min(1),
+            CatchUp::All => missed,
+        }
+    }
+}
+
+impl TryFrom<&ScheduleEntry> for JobSchedule {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: &ScheduleEntry) -> Result<Self> {
+        Ok(Self {
+            catch_up: entry.catch_up,
+            last_run_at: entry.last_run_at,
+            ..Self::parse(&entry.job, &entry.cron, &entry.timezone, entry.enabled)?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn wednesday_fires_in_its_timezone() {
+        let schedule =
+            JobSchedule::parse("wednesday", "0 0 9 * * Wed", "Europe/Moscow", true).unwrap();
+        // 2026-10-21 is Wednesday, 9:00 in Moscow is 6:00 UTC
+        let before = Utc.with_ymd_and_hms(2026, 10, 21, 5, 59, 50).unwrap();
+        let after = Utc.with_ymd_and_hms(2026, 10, 21, 6, 0, 0).unwrap();
+        assert!(schedule.fires_between(before, after));
+        assert!(!schedule.fires_between(after, after + chrono::Duration::days(6)));
+        assert_eq!(
+            schedule.next_after(after).unwrap().with_timezone(&Utc),
+            after + chrono::Duration::days(7)
+        );
+
+        assert!(JobSchedule::parse("wednesday", "0 0 9 * * Wed", "Mars/Olympus", true).is_err());
+        assert!(JobSchedule::parse("thursday", "0 0 9 * * Thu", "UTC", true).is_err());
+    }
+
+    #[test]
+    fn missed_runs_follow_catch_up() {
+        let mut schedule = JobSchedule::parse("crypto", "0 0 6,18 * * *", "UTC", true).unwrap();
+        let now = Utc.with_ymd_and_hms(2026, 10, 21, 12, 0, 0).unwrap();
+        assert_eq!(schedule.catch_up_runs(now), 0);
+
+        // Down since 5:00 two days ago, five runs were missed
+        schedule.last_run_at = Some(now - chrono::Duration::hours(55));
+        assert_eq!(schedule.catch_up_runs(now), 0);
+        schedule.catch_up = CatchUp::Once;
+        assert_eq!(schedule.catch_up_runs(now), 1);
+        schedule.catch_up = CatchUp::All;
+        assert_eq!(schedule.catch_up_runs(now), 5);
+
+        schedule.last_run_at = Some(now - chrono::Duration::days(30));
+        assert_eq!(schedule.catch_up_runs(now), MAX_CATCH_UP_RUNS);
+    }
+}
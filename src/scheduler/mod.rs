@@ -1,233 +1,495 @@
+mod jobs;
 mod rate_check_providers;
 #[macro_use]
 mod retry;
 
-use crate::cache::{CachePool, RateCheck};
-use crate::database::{Database, Pool};
-
-use clokwerk::{Interval::*, Job, TimeUnits};
+use crate::alerts::{Alert, AlertCheck, AlertKind};
+use crate::cache::{Cache, RateCheck};
+use crate::coins::CoinRegistry;
+use crate::database::{Database, Pool, Topic};
+use crate::delivery::{Delivery, Outbox, Outcome};
+use crate::leader::Leader;
+use crate::metrics;
+use crate::rates::Rate;
+use crate::toads::ToadChat;
+
+use futures::future::join_all;
+use std::collections::HashMap;
 use std::time::Duration;
-use teloxide::{prelude::*, types::ChatId, ApiError, RequestError};
+use tokio::sync::watch;
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use self::rate_check_providers::{CoinRateCheckProvider, RateCheckProvider};
 
-use rate_check_providers::{
-    BNBRateCheckProvider, BTCRateCheckProvider, ETHRateCheckProvider, NOTRateCheckProvider,
-};
+pub use self::jobs::{CatchUp, Job, JobSchedule, ScheduleEntry};
 
-use self::rate_check_providers::RateCheckProvider;
+/// A failing chat is retried up to this many times, after 15 minutes, then 30, 1 and 2 hours,
+/// so a short outage doesn't cost it the week's toad.
+const MAX_TOAD_ATTEMPTS: i32 = 5;
+const TOAD_RETRY_AFTER: Duration = Duration::from_secs(15 * 60);
+const PRICE_TICKS_RETENTION_DAYS: i64 = 7;
+const HOURLY_CANDLES_RETENTION_DAYS: i64 = 90;
 
 #[derive(Debug, Clone)]
 enum Task {
     Wednesday,
     Crypto,
-    BTC,
-    ETH,
-    BNB,
-    NOT,
+    RateCheck(String),
+    Alerts,
+    PriceHistory,
     Heartbeat,
 }
 
+impl Task {
+    fn name(&self) -> &'static str {
+        match self {
+            Task::Wednesday => Job::Wednesday.as_str(),
+            Task::Crypto => Job::Crypto.as_str(),
+            Task::RateCheck(_) => Job::RateCheck.as_str(),
+            Task::Alerts => Job::Alerts.as_str(),
+            Task::PriceHistory => Job::PriceHistory.as_str(),
+            Task::Heartbeat => Job::Heartbeat.as_str(),
+        }
+    }
+}
+
+/// Tasks of one run of a job, the run is saved as the last one once they are done.
+#[derive(Debug)]
+struct Run {
+    job: Job,
+    at: chrono::DateTime<chrono::Utc>,
+    tasks: Vec<Task>,
+}
+
+/// Jobs are run by the replica holding the scheduler lease, others stand by.
 pub struct Scheduler {
-    _schedule_handle: JoinHandle<()>,
+    ticker: JoinHandle<()>,
+    worker: JoinHandle<()>,
+    election: JoinHandle<Leader>,
 }
 
 impl Scheduler {
-    pub fn new(bot: teloxide::Bot, pool: Pool, cache_pool: CachePool) -> Self {
-        let mut scheduler = clokwerk::AsyncScheduler::with_tz(
-            chrono::FixedOffset::east_opt(3 * 3600).expect("Could not set tz for scheduler"),
-        );
+    pub fn new(
+        outbox: Outbox,
+        pool: Pool,
+        cache: Cache,
+        coins: CoinRegistry,
+        tick: Duration,
+        shutdown: CancellationToken,
+    ) -> Self {
+        let (tx, rx) = tokio::sync::mpsc::channel::<Run>(32);
+
+        let leader = Leader::new(pool.clone());
+        let leading = leader.subscribe();
+        let election = leader.elect(shutdown.clone());
+
+        let ticker = tokio::spawn(Self::ticker(
+            pool.clone(),
+            coins.clone(),
+            tick,
+            leading,
+            tx,
+            shutdown.clone(),
+        ));
+
+        let worker = tokio::spawn(Self::worker(outbox, pool, cache, coins, rx, shutdown));
+
+        Self {
+            ticker,
+            worker,
+            election,
+        }
+    }
 
-        let (tx, rx) = tokio::sync::mpsc::channel::<Task>(10);
-        async fn emit_task(tx: tokio::sync::mpsc::Sender<Task>, task: Task) {
-            if let Err(e) = tx.send(task.clone()).await {
-                tracing::error!("Failed to emit {:?} task: {}", task, e);
+    /// Waits until the scheduler stops after the shutdown, the task in progress is finished.
+    /// The lease is released then, so a standby replica takes over right away.
+    pub async fn stopped(self) {
+        for handle in [self.ticker, self.worker] {
+            if let Err(e) = handle.await {
+                tracing::error!("Scheduler task failed: {}", e);
             }
         }
+        match self.election.await {
+            Ok(leader) => leader.release().await,
+            Err(e) => tracing::error!("Scheduler election failed: {}", e),
+        }
+    }
 
-        let t = tx.clone();
-        scheduler
-            .every(Wednesday)
-            .at("9:00 am")
-            .run(move || emit_task(t.clone(), Task::Wednesday));
-
-        let t = tx.clone();
-        scheduler
-            .every(1.day())
-            .at("6:00 am")
-            .and_every(1.day())
-            .at("6:00 pm")
-            .run(move || emit_task(t.clone(), Task::Crypto));
-
-        let t = tx.clone();
-        scheduler
-            .every(10.minute())
-            .run(move || emit_task(t.clone(), Task::BTC));
-        let t = tx.clone();
-        scheduler
-            .every(10.minute())
-            .run(move || emit_task(t.clone(), Task::ETH));
-        let t = tx.clone();
-        scheduler
-            .every(10.minute())
-            .run(move || emit_task(t.clone(), Task::BNB));
-        let t = tx.clone();
-        scheduler
-            .every(10.minute())
-            .run(move || emit_task(t.clone(), Task::NOT));
-
-        let t = tx.clone();
-        scheduler.every(1.hour()).run(move || {
-            tracing::info!("emitting heartbeat");
-            emit_task(t.clone(), Task::Heartbeat)
-        });
-
-        let handle = tokio::task::spawn(async move {
-            loop {
-                scheduler.run_pending().await;
-                tokio::time::sleep(Duration::from_secs(30)).await;
+    /// Schedules are re-read from the database on every tick, so changes
+    /// are picked up without a restart. Runs are only emitted while this replica
+    /// holds the lease. On taking it, runs missed while no replica was leading (the
+    /// bot was down or the previous leader is gone) are caught up by policy, the
+    /// Wednesday job resumes the broadcast interrupted by a restart.
+    async fn ticker(
+        pool: Pool,
+        coins: CoinRegistry,
+        tick: Duration,
+        mut leading: watch::Receiver<bool>,
+        tx: tokio::sync::mpsc::Sender<Run>,
+        shutdown: CancellationToken,
+    ) {
+        let db = match Database::new(pool).await {
+            Ok(db) => db,
+            Err(e) => {
+                tracing::error!("Scheduler ticker failed to start: {}", e);
+                return;
             }
-        });
+        };
+        let mut schedules: Vec<JobSchedule> = vec![];
+        let mut last_tick = chrono::Utc::now();
+        let mut led = false;
 
-        let _thread = tokio::spawn(Self::worker(bot, pool, cache_pool, rx));
+        loop {
+            tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => return,
+                Ok(()) = leading.changed() => {}
+                _ = tokio::time::sleep(tick) => {}
+            }
 
-        Self {
-            _schedule_handle: handle,
+            let now = chrono::Utc::now();
+            if !*leading.borrow_and_update() {
+                led = false;
+                last_tick = now;
+                continue;
+            }
+
+            match Self::load_schedules(&db).await {
+                Ok(loaded) => schedules = loaded,
+                Err(e) => tracing::error!("Failed to reload schedules, keeping previous: {}", e),
+            }
+
+            for schedule in schedules.iter().filter(|schedule| schedule.enabled) {
+                let mut runs = 0;
+                if !led {
+                    runs = schedule.catch_up_runs(now);
+                    if runs > 0 {
+                        tracing::info!("Catching up {} missed runs of {}", runs, schedule.job);
+                    }
+                }
+                // Missed runs include this tick, except for the ones which are not caught up
+                if runs == 0 && schedule.fires_between(last_tick, now) {
+                    runs = 1;
+                }
+                for _ in 0..runs {
+                    Self::emit_run(&tx, schedule.job, now, &coins).await;
+                }
+            }
+            led = true;
+            last_tick = now;
+        }
+    }
+
+    /// The last run is what missed runs are counted from after a restart.
+    async fn mark_run(db: &Database, job: Job, at: chrono::DateTime<chrono::Utc>) {
+        if let Err(e) = db.mark_schedule_run(job.as_str(), at).await {
+            tracing::error!("Failed to save the last run of {}: {}", job, e);
+        }
+    }
+
+    async fn load_schedules(db: &Database) -> anyhow::Result<Vec<JobSchedule>> {
+        let schedules = db
+            .get_schedules()
+            .await?
+            .iter()
+            .filter_map(|entry| match JobSchedule::try_from(entry) {
+                Ok(schedule) => Some(schedule),
+                Err(e) => {
+                    tracing::error!("Skipping schedule of {}: {}", entry.job, e);
+                    None
+                }
+            })
+            .collect();
+        Ok(schedules)
+    }
+
+    fn tasks(job: Job, coins: &CoinRegistry) -> Vec<Task> {
+        match job {
+            Job::Wednesday => vec![Task::Wednesday],
+            Job::Crypto => vec![Task::Crypto],
+            Job::RateCheck => coins
+                .iter()
+                .map(|coin| Task::RateCheck(coin.ticker.clone()))
+                .collect(),
+            Job::Alerts => vec![Task::Alerts],
+            Job::PriceHistory => vec![Task::PriceHistory],
+            Job::Heartbeat => {
+                tracing::info!("emitting heartbeat");
+                vec![Task::Heartbeat]
+            }
+        }
+    }
+
+    async fn emit_run(
+        tx: &tokio::sync::mpsc::Sender<Run>,
+        job: Job,
+        at: chrono::DateTime<chrono::Utc>,
+        coins: &CoinRegistry,
+    ) {
+        let tasks = Self::tasks(job, coins);
+        if let Err(e) = tx.send(Run { job, at, tasks }).await {
+            tracing::error!("Failed to emit a run of {}: {}", job, e);
         }
     }
 
     async fn worker(
-        bot: teloxide::Bot,
+        outbox: Outbox,
         pool: Pool,
-        cache_pool: CachePool,
-        mut rx: tokio::sync::mpsc::Receiver<Task>,
+        cache: Cache,
+        coins: CoinRegistry,
+        mut rx: tokio::sync::mpsc::Receiver<Run>,
+        shutdown: CancellationToken,
     ) {
+        let db = match Database::new(pool.clone()).await {
+            Ok(db) => db,
+            Err(e) => {
+                tracing::error!("Scheduler worker failed to start: {}", e);
+                return;
+            }
+        };
         loop {
-            tokio::select! {
-                Some(task) = rx.recv() => {
-                    tracing::info!("Scheduler worker received a task: {:?}", task);
-
-                    let res = match task {
-                            Task::Wednesday => Self::send_toads(bot.clone(), pool.clone()).await,
-                            Task::Crypto => Self::send_rates(bot.clone(), pool.clone()).await,
-                            Task::BTC => {
-                                let provider = BTCRateCheckProvider::from(cache_pool.clone());
-                                Self::check_rate(bot.clone(), pool.clone(), provider).await
-                            },
-                            Task::ETH => {
-                                let provider = ETHRateCheckProvider::from(cache_pool.clone());
-                                Self::check_rate(bot.clone(), pool.clone(), provider).await
-                            },
-                            Task::BNB => {
-                                let provider = BNBRateCheckProvider::from(cache_pool.clone());
-                                Self::check_rate(bot.clone(), pool.clone(), provider).await
-                            },
-                            Task::NOT => {
-                                let provider = NOTRateCheckProvider::from(cache_pool.clone());
-                                Self::check_rate(bot.clone(), pool.clone(), provider).await
-                            },
-                            Task::Heartbeat => {
-                                tracing::info!("received heartbeat");
-                                Ok(())
-                            }
-                    };
-                    if let Err(e) = res {
-                        tracing::error!("Scheduled task {:?} finished with error: {}", task, e);
-                    }
+            let run = tokio::select! {
+                // Runs still in the queue are not started after the shutdown
+                biased;
+                _ = shutdown.cancelled() => None,
+                Some(run) = rx.recv() => Some(run),
+            };
+            let Some(run) = run else {
+                tracing::info!("Scheduler worker is stopped");
+                return;
+            };
+
+            for task in &run.tasks {
+                // An unfinished run is not saved, so it is caught up after the restart
+                if shutdown.is_cancelled() {
+                    tracing::info!("Scheduler worker is stopped amid a run of {}", run.job);
+                    return;
+                }
+                tracing::info!("Scheduler worker received a task: {:?}", task);
+                let started = std::time::Instant::now();
+                let res = Self::run_task(task, &outbox, &pool, &cache, &coins).await;
+                metrics::observe(
+                    &metrics::TASK_DURATION,
+                    &[task.name(), metrics::outcome(&res)],
+                    started,
+                );
+                if let Err(e) = res {
+                    tracing::error!("Scheduled task {:?} finished with error: {}", task, e);
                 }
             }
+            Self::mark_run(&db, run.job, run.at).await;
         }
     }
 
-    #[tracing::instrument]
-    async fn send_toads(bot: Bot, pool: Pool) -> anyhow::Result<()> {
-        tracing::info!("Sending toads");
+    async fn run_task(
+        task: &Task,
+        outbox: &Outbox,
+        pool: &Pool,
+        cache: &Cache,
+        coins: &CoinRegistry,
+    ) -> anyhow::Result<()> {
+        match task {
+            Task::Wednesday => Self::send_toads(outbox.clone(), pool.clone()).await,
+            Task::Crypto => Self::send_rates(outbox.clone(), pool.clone(), coins.clone()).await,
+            Task::RateCheck(ticker) => match coins.get(ticker).cloned() {
+                Some(coin) => {
+                    let provider = CoinRateCheckProvider::new(cache.clone(), coin);
+                    Self::check_rate(outbox.clone(), pool.clone(), provider).await
+                }
+                None => Err(anyhow::anyhow!("Coin {} is not in the registry", ticker)),
+            },
+            Task::Alerts => Self::check_alerts(outbox.clone(), pool.clone(), coins.clone()).await,
+            Task::PriceHistory => Self::maintain_price_history(pool.clone()).await,
+            Task::Heartbeat => {
+                tracing::info!("received heartbeat");
+                Ok(())
+            }
+        }
+    }
+
+    /// Also resumes the broadcast interrupted by a restart: its chats are still due.
+    #[tracing::instrument(skip(outbox))]
+    async fn send_toads(outbox: Outbox, pool: Pool) -> anyhow::Result<()> {
         let db = Database::new(pool.clone()).await?;
-        let chats = retry! { db.get_all_active_chats().await, 3, 1000 }?;
-        let url = crate::toads::get_toad();
+        let now = chrono::Utc::now();
+        let chats: Vec<ToadChat> = retry! { db.get_toad_chats().await, 3, 1000 }?
+            .into_iter()
+            .filter(|chat| chat.is_due(now))
+            .collect();
+
+        if chats.is_empty() {
+            return Ok(());
+        }
+
+        tracing::info!("Sending toads to {} chats", chats.len());
+        Self::send_toads_to(&outbox, pool, chats).await?;
+        Ok(())
+    }
+
+    /// Sends the next toad of the rotation to each chat, whether it is due or not.
+    ///
+    /// The toad of a week is sent once: chats are claimed in the `deliveries` table and
+    /// the ones which got it already are skipped. Each chat is marked as soon as its toad
+    /// is sent, so a broadcast cut by the shutdown resumes with the rest.
+    #[tracing::instrument(skip(outbox, pool, chats))]
+    pub async fn send_toads_to(
+        outbox: &Outbox,
+        pool: Pool,
+        chats: Vec<ToadChat>,
+    ) -> anyhow::Result<Vec<(i64, Outcome)>> {
+        let db = Database::new(pool).await?;
+        let now = chrono::Utc::now();
+        let campaigns: HashMap<i64, String> = chats
+            .iter()
+            .map(|chat| (chat.chat_id, chat.campaign(now)))
+            .collect();
+        let claims: Vec<(String, i64)> = campaigns
+            .iter()
+            .map(|(chat, campaign)| (campaign.clone(), *chat))
+            .collect();
+        let claimed = db
+            .claim_deliveries(&claims, MAX_TOAD_ATTEMPTS, TOAD_RETRY_AFTER)
+            .await?;
+        if claimed.len() < chats.len() {
+            tracing::info!(
+                "Skipping {} chats which got the toad already",
+                chats.len() - claimed.len()
+            );
+        }
         let mapping = retry! { db.get_mapping().await }?;
 
-        for chat in chats {
-            let name = mapping
-                .get(&chat)
-                .cloned()
-                .unwrap_or(String::from("(empty)"));
-
-            tracing::info!("Sending toad to dude {}, name = {}", chat, name);
-            if let Err(e) = bot.send_message(ChatId(chat), &url).send().await {
-                sentry::capture_error(&e);
-
-                match e {
-                    RequestError::Api(ref kind) => match kind {
-                        ApiError::BotBlocked => {
-                            tracing::warn!(
-                                "Chat {} ({}) blocked the bot. Removing from active chats",
-                                chat,
-                                name
-                            );
-                            db.remove(chat).await?;
-                        }
-                        ApiError::ChatNotFound => {
-                            tracing::warn!(
-                                "Chat {} ({}) was not found. Removing from active chats",
-                                chat,
-                                name
-                            );
-                            db.remove(chat).await?;
-                        }
-                        ApiError::UserDeactivated => {
-                            tracing::warn!(
-                                "Chat {} ({}) was deactivated. Removing from active chats",
-                                chat,
-                                name
-                            );
-                            db.remove(chat).await?;
-                        }
-                        _ => {}
-                    },
-                    RequestError::MigrateToChatId(chat_id) => {
-                        tracing::warn!("Chat {} was migrated to {}. Replacing", chat, chat_id);
-                        db.remove(chat).await?;
-                        db.add(chat_id.0).await?;
-                        bot.send_message(chat_id, &url).send().await.ok();
+        // A failing chat is left to the next attempt, the others are sent anyway
+        let mut toads = vec![];
+        let mut outcomes = vec![];
+        for chat in claimed {
+            match crate::toads::get_toad(&db, chat).await {
+                Ok(toad) => toads.push((chat, toad)),
+                Err(e) => {
+                    tracing::error!("Failed to pick a toad for {}: {}", chat, e);
+                    let outcome = Outcome::Failed(e.to_string());
+                    Self::finish_toad(&db, &campaigns[&chat], chat, None, &outcome).await;
+                    outcomes.push((chat, outcome));
+                }
+            }
+        }
+
+        let sent = join_all(toads.into_iter().map(|(chat, toad)| {
+            let (db, mapping, campaign) = (&db, &mapping, &campaigns[&chat]);
+            async move {
+                let delivery = Delivery::text(chat, toad.toad.url())
+                    .topic(Topic::Wednesday)
+                    .kind("toad");
+                let outcome = outbox
+                    .deliver(delivery)
+                    .await
+                    .unwrap_or_else(|e| Outcome::Failed(e.to_string()));
+                let name = mapping
+                    .get(&chat)
+                    .cloned()
+                    .unwrap_or(String::from("(empty)"));
+
+                // A migrated chat is moved together with its ledger rows
+                let sent_to = match outcome {
+                    Outcome::Sent => chat,
+                    Outcome::Migrated(new_chat) => new_chat,
+                    ref outcome => {
+                        tracing::warn!(
+                            "Toad was not sent to {}, name = {}: {:?}",
+                            chat,
+                            name,
+                            outcome
+                        );
+                        Self::finish_toad(db, campaign, chat, Some(toad.toad.id), outcome).await;
+                        return (chat, outcome.clone());
                     }
-                    _ => {}
+                };
+                Self::finish_toad(db, campaign, sent_to, Some(toad.toad.id), &outcome).await;
+                tracing::info!("Sent toad to dude {}, name = {}", sent_to, name);
+                let recorded = async {
+                    db.mark_toad_sent(sent_to).await?;
+                    db.add_toad_delivery(sent_to, &toad).await
+                };
+                if let Err(e) = recorded.await {
+                    tracing::error!("Failed to record the toad sent to {}: {}", sent_to, e);
                 }
+                (chat, outcome)
             }
+        }))
+        .await;
+        outcomes.extend(sent);
+        Ok(outcomes)
+    }
+
+    /// The ledger is updated best effort, a row left in flight is reclaimed later.
+    async fn finish_toad(
+        db: &Database,
+        campaign: &str,
+        chat: i64,
+        toad_id: Option<i32>,
+        outcome: &Outcome,
+    ) {
+        if let Err(e) = db.finish_delivery(campaign, chat, toad_id, outcome).await {
+            tracing::error!("Failed to finish the toad delivery to {}: {}", chat, e);
         }
-        Ok(())
     }
 
-    #[tracing::instrument]
-    async fn send_rates(bot: Bot, pool: Pool) -> anyhow::Result<()> {
+    #[tracing::instrument(skip(coins))]
+    async fn send_rates(outbox: Outbox, pool: Pool, coins: CoinRegistry) -> anyhow::Result<()> {
         tracing::info!("Send rates");
 
         let db = Database::new(pool.clone()).await?;
-        let chats = retry! { db.get_all_active_crypto_chats().await, 3, 1000 }?;
+        let chats = retry! { db.get_subscribers(Topic::Crypto).await, 3, 1000 }?;
 
-        let rate = crate::rates::get_eth_rate().await?;
+        let eth = coins
+            .get("ETH")
+            .ok_or(anyhow::anyhow!("ETH is missing in the coin registry"))?;
+        let rate = crate::rates::get_coin_rate(eth).await?;
+        Self::record_price(&db, &eth.ticker, &rate).await;
+        let rate = rate.price;
 
         let text = if rate > 5_000. {
-            format!("Когда майбук? Сегодня! Курс ETH = {}$", rate)
+            format!(
+                "Когда майбук? Сегодня! Курс ETH = {}$",
+                eth.format_price(rate)
+            )
         } else {
-            format!("Когда майбук? Не сегодня. Курс ETH = {}$", rate)
+            format!(
+                "Когда майбук? Не сегодня. Курс ETH = {}$",
+                eth.format_price(rate)
+            )
         };
 
-        for chat in chats {
-            bot.send_message(ChatId(chat), &text).send().await?;
-        }
+        let deliveries = chats
+            .into_iter()
+            .map(|chat| {
+                Delivery::text(chat, &text)
+                    .topic(Topic::Crypto)
+                    .kind("rates")
+            })
+            .collect();
+        Self::log_failures("rates", outbox.deliver_all(deliveries).await?);
         Ok(())
     }
 
     #[tracing::instrument(skip(provider), fields(coin = provider.coin()))]
     async fn check_rate(
-        bot: Bot,
+        outbox: Outbox,
         pool: Pool,
         provider: impl RateCheckProvider,
     ) -> anyhow::Result<()> {
         let db = Database::new(pool.clone()).await?;
 
-        let prev_rates = provider.get_last_rates().await?;
         let current_rate = provider.get_current_rate().await?;
+        Self::record_price(&db, provider.coin(), &current_rate).await;
+        let current_rate = current_rate.price;
+
+        let step = match provider.step() {
+            Some(step) => step,
+            None => return Ok(()),
+        };
+
+        let prev_rates = provider.get_last_rates().await?;
 
         let mut last_rate_check: Option<RateCheck> = None;
 
@@ -241,8 +503,8 @@ impl Scheduler {
         }
 
         if !prev_rates.is_empty() {
-            let prev = (prev_rates[0].rate / provider.step()) as i64;
-            let curr = (current_rate / provider.step()) as i64;
+            let prev = (prev_rates[0].rate / step) as i64;
+            let curr = (current_rate / step) as i64;
 
             if prev == curr {
                 return Ok(());
@@ -268,7 +530,7 @@ impl Scheduler {
             return Ok(());
         }
 
-        let chats = retry! { db.get_all_active_crypto_chats().await, 3, 1000 }?;
+        let chats = retry! { db.get_subscribers(Topic::Crypto).await, 3, 1000 }?;
 
         tracing::info!(
             "send {} rate change {} for chats {:?}",
@@ -277,15 +539,131 @@ impl Scheduler {
             chats
         );
 
-        for chat in chats {
-            let text = format!(
-                "{} rate now is {}$ {}",
-                provider.coin(),
-                last_rate_check.rate,
-                if last_rate_check.grow { "📈" } else { "📉" }
+        let text = format!(
+            "{} rate now is {}$ {}",
+            provider.coin(),
+            provider.format_price(last_rate_check.rate),
+            if last_rate_check.grow { "📈" } else { "📉" }
+        );
+        let deliveries = chats
+            .into_iter()
+            .map(|chat| {
+                Delivery::text(chat, &text)
+                    .topic(Topic::Crypto)
+                    .kind("rate_check")
+            })
+            .collect();
+        Self::log_failures("rate check", outbox.deliver_all(deliveries).await?);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(coins))]
+    async fn check_alerts(outbox: Outbox, pool: Pool, coins: CoinRegistry) -> anyhow::Result<()> {
+        let db = Database::new(pool.clone()).await?;
+        let alerts = retry! { db.get_active_alerts().await, 3, 1000 }?;
+
+        let mut by_coin: HashMap<String, Vec<Alert>> = HashMap::new();
+        for alert in alerts {
+            by_coin.entry(alert.coin.clone()).or_default().push(alert);
+        }
+
+        for (ticker, alerts) in by_coin {
+            let coin = match coins.get(&ticker) {
+                Some(coin) => coin,
+                None => {
+                    tracing::warn!(
+                        "Coin {} of {} alerts is not in the registry",
+                        ticker,
+                        alerts.len()
+                    );
+                    continue;
+                }
+            };
+            let price = match crate::rates::get_coin_rate(coin).await {
+                Ok(rate) => {
+                    Self::record_price(&db, &coin.ticker, &rate).await;
+                    rate.price
+                }
+                Err(e) => {
+                    tracing::error!("Failed to check {} alerts: {}", ticker, e);
+                    continue;
+                }
+            };
+
+            let now = chrono::Utc::now();
+            for alert in alerts {
+                match alert.check(price, now) {
+                    AlertCheck::Keep => {}
+                    AlertCheck::Rebase => db.rebase_alert(alert.id, price).await?,
+                    AlertCheck::Fire => {
+                        if !db.fire_alert(alert.id).await? {
+                            continue;
+                        }
+                        let text = match alert.kind {
+                            AlertKind::Change => format!(
+                                "🔔 {} moved {:+.2}%, now it is {}$ (alert {})",
+                                coin.ticker,
+                                alert.change(price),
+                                coin.format_price(price),
+                                alert.describe()
+                            ),
+                            _ => format!(
+                                "🔔 {} is {}$ now (alert {})",
+                                coin.ticker,
+                                coin.format_price(price),
+                                alert.describe()
+                            ),
+                        };
+                        outbox
+                            .enqueue(Delivery::text(alert.chat_id, text).kind("alert"))
+                            .await?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn log_failures(what: &str, outcomes: Vec<Outcome>) {
+        let failed = outcomes.iter().filter(|outcome| !outcome.is_sent()).count();
+        if failed > 0 {
+            tracing::warn!(
+                "Failed to deliver {} to {} of {} chats",
+                what,
+                failed,
+                outcomes.len()
             );
-            bot.send_message(ChatId(chat), text).send().await?;
         }
+    }
+
+    /// Price history is best effort, a failed insert shouldn't break the task.
+    async fn record_price(db: &Database, coin: &str, rate: &Rate) {
+        if let Err(e) = db.add_price_tick(coin, rate.source, rate.price).await {
+            tracing::error!("Failed to record {} price: {}", coin, e);
+        }
+    }
+
+    #[tracing::instrument]
+    async fn maintain_price_history(pool: Pool) -> anyhow::Result<()> {
+        let db = Database::new(pool.clone()).await?;
+        let now = chrono::Utc::now();
+
+        // Ticks are added through the whole hour, so the previous one is rebuilt too
+        retry! { db.downsample_prices(now - chrono::Duration::hours(2)).await, 3, 1000 }?;
+
+        let (ticks, candles) = db
+            .prune_price_history(
+                now - chrono::Duration::days(PRICE_TICKS_RETENTION_DAYS),
+                now - chrono::Duration::days(HOURLY_CANDLES_RETENTION_DAYS),
+            )
+            .await?;
+        tracing::info!(
+            "Pruned {} price ticks and {} hourly candles",
+            ticks,
+            candles
+        );
 
         Ok(())
     }
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+
+use crate::delivery::Outbox;
+use crate::scheduler::Scheduler;
+
+/// How long the scheduler and the outbox may take to finish their work.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Cancels `shutdown` on SIGINT or SIGTERM.
+pub fn listen(shutdown: CancellationToken) {
+    tokio::spawn(async move {
+        signal().await;
+        tracing::info!("Shutting down");
+        shutdown.cancel();
+    });
+}
+
+#[cfg(unix)]
+async fn signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            tracing::error!("Failed to listen for SIGTERM: {}", e);
+            tokio::signal::ctrl_c().await.ok();
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() {
+    tokio::signal::ctrl_c().await.ok();
+}
+
+/// Lets the task in progress and the messages being sent finish.
+pub async fn drain(scheduler: Option<Scheduler>, outbox: &Outbox) {
+    let drained = async {
+        if let Some(scheduler) = scheduler {
+            scheduler.stopped().await;
+        }
+        outbox.drain().await;
+    };
+    if tokio::time::timeout(DRAIN_TIMEOUT, drained).await.is_err() {
+        tracing::warn!(
+            "Shutdown timed out after {:?}, unfinished sends are dropped",
+            DRAIN_TIMEOUT
+        );
+    }
+}
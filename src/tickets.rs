@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "ticket_status", rename_all = "lowercase")]
+pub enum TicketStatus {
+    Open,
+    Closed,
+}
+
+/// Private conversation of a user with the admin.
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    pub id: i64,
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub status: TicketStatus,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Ticket {
+    pub fn user(&self) -> String {
+        match self.username {
+            Some(ref username) => format!("{} ({})", username, self.user_id),
+            None => self.user_id.to_string(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "#{} from {}, last message at {}",
+            self.id,
+            self.user(),
+            self.updated_at.format("%Y-%m-%d %H:%M")
+        )
+    }
+}
@@ -1,62 +1,333 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
 use rand::seq::IndexedRandom;
 
-const TOADS: &[&str] = &[
-    "9K4-jllrPrE",
-    "bbat6cvgEJ8",
-    "Oct2xKMGOno",
-    "DREDJ4fkz-g",
-    "gxm5SwfkwcI",
-    "oVxFk_IIB2o",
-    "SePVlroq6AI",
-    "JHO61_wDC30",
-    "EBNEPil4da0",
-    "pXv4zQ6dYPQ",
-    "hzGQSlrB1_o",
-    "Y_xlWdgi1ew",
-    "szqNmefKXxc",
-    "OzQ-KvxLVT0",
-    "zl6phK1mXC4",
-    "7aTtNNjIyi4",
-    "1CH-7qjz4D4",
-    "YSDAAh6Lps4",
-    "fyJGKEswuSc",
-    "csqJK8wwaHw",
-    "KSwnFzlPEuY",
-    "aew9WTLqjDc",
-    "m2Z0CyuyfMI",
-    "VaPMUACYWww",
-    "_87k7gxeVsw",
-    "3RSL5k3yZOM",
-    "VXc47lVx7Eo",
-    "0W51GIxnwKc",
-    "VfaNCw2bF48",
-    "It8RbsGIe48",
-    "NBPlPowAsNc",
-    "IaE0g3oVIZ0",
-    "VzigPnZ8OYE",
-    "meuYC7FP7HU",
-    "N3e7G9OxfhI",
-    "IR0QUwGmo4A",
-    "ESNBnxtpKqI",
-    "036ItQLi-sQ",
-    "Kz26jod9-cQ",
-    "LrleLDD8CJM",
-    "ZHS5yAwApUs",
-    "PE8GlPpuLuY",
-    "4Sr5pRpDZMk",
-    "qCsYa8PeVfU",
-    "-R40VcLKyIw",
-    "7dr2s59XnBE",
-    "iTl1l3GFMJ8",
-    "In9Bs1wiF5s",
-    "zHpFuOlPrlQ",
-    "Xf_wuAQ-t44",
-    "frNFBv2QIoE",
-    "PAnKl7862qc",
-];
-
-pub fn get_toad() -> String {
-    let mut r = rand::rng();
-    let video_id = *TOADS.choose(&mut r).unwrap();
-    format!("https://youtu.be/{}", video_id)
+use crate::database::Database;
+
+/// Toads added recently are picked more often.
+const NEW_TOAD_DAYS: i64 = 30;
+const NEW_TOAD_BOOST: f64 = 2.;
+
+/// Row of the `toads` catalog, a YouTube video.
+#[derive(Debug, Clone)]
+pub struct Toad {
+    pub id: i32,
+    pub video_id: String,
+    pub weight: f64,
+}
+
+impl Toad {
+    pub fn url(&self) -> String {
+        format!("https://youtu.be/{}", self.video_id)
+    }
+}
+
+/// Catalog toad with its delivery history in a chat.
+#[derive(Debug, Clone)]
+pub struct RotationToad {
+    pub toad: Toad,
+    pub created_at: DateTime<Utc>,
+    pub sent_in_cycle: bool,
+    pub last_sent_at: Option<DateTime<Utc>>,
+}
+
+impl RotationToad {
+    fn effective_weight(&self, now: DateTime<Utc>) -> f64 {
+        if now - self.created_at < Duration::days(NEW_TOAD_DAYS) {
+            self.toad.weight * NEW_TOAD_BOOST
+        } else {
+            self.toad.weight
+        }
+    }
+}
+
+/// Toads are not repeated in a chat until the whole catalog is cycled.
+#[derive(Debug, Clone)]
+pub struct ToadRotation {
+    pub cycle: i32,
+    pub toads: Vec<RotationToad>,
+}
+
+/// Picked toad and the rotation cycle its delivery belongs to.
+#[derive(Debug, Clone)]
+pub struct NextToad {
+    pub toad: Toad,
+    pub cycle: i32,
+}
+
+impl ToadRotation {
+    pub fn pick(&self, now: DateTime<Utc>) -> Option<NextToad> {
+        let mut cycle = self.cycle;
+        let mut candidates: Vec<&RotationToad> = self
+            .toads
+            .iter()
+            .filter(|toad| !toad.sent_in_cycle)
+            .collect();
+
+        if candidates.is_empty() {
+            // Everything was sent, start a new cycle but don't repeat the last toad
+            cycle += 1;
+            let last = self
+                .toads
+                .iter()
+                .max_by_key(|toad| toad.last_sent_at)
+                .map(|toad| toad.toad.id);
+            candidates = self
+                .toads
+                .iter()
+                .filter(|toad| self.toads.len() == 1 || Some(toad.toad.id) != last)
+                .collect();
+        }
+
+        candidates
+            .choose_weighted(&mut rand::rng(), |toad| toad.effective_weight(now))
+            .ok()
+            .map(|toad| NextToad {
+                toad: toad.toad.clone(),
+                cycle,
+            })
+    }
+}
+
+/// The delivery should be saved with `Database::add_toad_delivery` once the toad is sent.
+pub async fn get_toad(db: &Database, chat_id: i64) -> Result<NextToad> {
+    db.get_toad_rotation(chat_id)
+        .await?
+        .pick(Utc::now())
+        .ok_or(anyhow!("Toad catalog is empty"))
+}
+
+/// Extracts the video ID from a YouTube link, a bare ID is accepted as well.
+pub fn parse_video_id(input: &str) -> Result<String> {
+    let input = input.trim();
+    let rest = input
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.")
+        .trim_start_matches("m.");
+    let id = if let Some(rest) = rest.strip_prefix("youtu.be/") {
+        rest
+    } else if let Some(rest) = rest.strip_prefix("youtube.com/") {
+        if let Some(query) = rest.strip_prefix("watch?") {
+            query
+                .split('&')
+                .find_map(|param| param.strip_prefix("v="))
+                .unwrap_or_default()
+        } else {
+            rest.strip_prefix("shorts/")
+                .or(rest.strip_prefix("embed/"))
+                .unwrap_or_default()
+        }
+    } else {
+        rest
+    };
+    let id = id.split(['?', '&', '#', '/']).next().unwrap_or_default();
+
+    let valid = id.len() == 11
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(id.to_owned())
+    } else {
+        Err(anyhow!("Could not find YouTube video in `{}`", input))
+    }
+}
+
+pub const DEFAULT_TIMEZONE: Tz = chrono_tz::Europe::Moscow;
+pub const DEFAULT_TOAD_TIME: NaiveTime = match NaiveTime::from_hms_opt(9, 0, 0) {
+    Some(time) => time,
+    None => unreachable!(),
+};
+
+/// Wednesday subscriber with its delivery settings.
+#[derive(Debug, Clone)]
+pub struct ToadChat {
+    pub chat_id: i64,
+    pub timezone: String,
+    pub toad_time: NaiveTime,
+    pub last_toad_at: Option<DateTime<Utc>>,
+}
+
+impl ToadChat {
+    pub fn timezone(&self) -> Tz {
+        self.timezone.parse().unwrap_or_else(|_| {
+            tracing::warn!(
+                "Chat {} has invalid timezone {}, using default",
+                self.chat_id,
+                self.timezone
+            );
+            DEFAULT_TIMEZONE
+        })
+    }
+
+    /// Wednesday of the chat's current week.
+    fn wednesday(&self, now: DateTime<Utc>) -> NaiveDate {
+        let local = now.with_timezone(&self.timezone()).date_naive();
+        local.week(Weekday::Mon).first_day() + Duration::days(2)
+    }
+
+    /// The delivery time of the chat's Wednesday has come and the toad wasn't sent
+    /// since that Wednesday. A week missed by a downtime is caught up till its end.
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        let tz = self.timezone();
+        let wednesday = self.wednesday(now);
+        if now.with_timezone(&tz).naive_local() < wednesday.and_time(self.toad_time) {
+            return false;
+        }
+        match self.last_toad_at {
+            Some(last) => last.with_timezone(&tz).date_naive() < wednesday,
+            None => true,
+        }
+    }
+
+    /// Key of the toad in the `deliveries` table. From the chat's Wednesday on, it is
+    /// the date of that Wednesday, so a forced broadcast is the week's toad. Earlier in
+    /// the week a forced toad is an extra one and doesn't take the place of Wednesday's.
+    pub fn campaign(&self, now: DateTime<Utc>) -> String {
+        let wednesday = self.wednesday(now);
+        if now.with_timezone(&self.timezone()).date_naive() < wednesday {
+            format!("toad-manual-{}", now.timestamp())
+        } else {
+            format!("toad-{}", wednesday)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn toads_are_not_repeated_within_cycle() {
+        let now = Utc::now();
+        let mut rotation = ToadRotation {
+            cycle: 0,
+            toads: (1..=3)
+                .map(|id| RotationToad {
+                    toad: Toad {
+                        id,
+                        video_id: format!("video{}", id),
+                        weight: id as f64,
+                    },
+                    created_at: now - Duration::days(365),
+                    sent_in_cycle: false,
+                    last_sent_at: None,
+                })
+                .collect(),
+        };
+
+        let mut sent = vec![];
+        for i in 0..3 {
+            let next = rotation.pick(now).unwrap();
+            assert_eq!(next.cycle, 0);
+            let toad = rotation
+                .toads
+                .iter_mut()
+                .find(|toad| toad.toad.id == next.toad.id)
+                .unwrap();
+            assert!(!toad.sent_in_cycle);
+            toad.sent_in_cycle = true;
+            toad.last_sent_at = Some(now + Duration::days(7 * i));
+            sent.push(next.toad.id);
+        }
+
+        let next = rotation.pick(now).unwrap();
+        assert_eq!(next.cycle, 1);
+        assert_ne!(next.toad.id, *sent.last().unwrap());
+    }
+
+    #[test]
+    fn parse_youtube_links() {
+        for link in [
+            "9K4-jllrPrE",
+            "https://youtu.be/9K4-jllrPrE",
+            "https://youtu.be/9K4-jllrPrE?si=abc",
+            "https://www.youtube.com/watch?v=9K4-jllrPrE&t=10s",
+            "youtube.com/shorts/9K4-jllrPrE",
+        ] {
+            assert_eq!(parse_video_id(link).unwrap(), "9K4-jllrPrE", "{}", link);
+        }
+        assert!(parse_video_id("https://example.com/9K4-jllrPrE").is_err());
+        assert!(parse_video_id("https://www.youtube.com/watch?list=x").is_err());
+    }
+
+    #[test]
+    fn toad_is_due_on_local_wednesday_morning() {
+        let mut chat = ToadChat {
+            chat_id: 1,
+            timezone: "America/New_York".to_owned(),
+            toad_time: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            last_toad_at: None,
+        };
+
+        // 2026-10-21 is Wednesday, 9:00 in New York is 13:00 UTC
+        let early = Utc.with_ymd_and_hms(2026, 10, 21, 12, 59, 0).unwrap();
+        let time = Utc.with_ymd_and_hms(2026, 10, 21, 13, 0, 0).unwrap();
+        assert!(!chat.is_due(early));
+        assert!(chat.is_due(time));
+
+        chat.last_toad_at = Some(time);
+        assert!(!chat.is_due(time + chrono::Duration::hours(1)));
+
+        chat.last_toad_at = Some(time - chrono::Duration::days(7));
+        assert!(chat.is_due(time + chrono::Duration::hours(1)));
+
+        // It is Tuesday in Honolulu yet
+        chat.timezone = "Pacific/Honolulu".to_owned();
+        chat.last_toad_at = None;
+        assert!(!chat.is_due(Utc.with_ymd_and_hms(2026, 10, 21, 9, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn missed_toad_is_caught_up_till_the_end_of_week() {
+        let mut chat = ToadChat {
+            chat_id: 1,
+            timezone: "Europe/Moscow".to_owned(),
+            toad_time: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            last_toad_at: Some(Utc.with_ymd_and_hms(2026, 10, 14, 6, 0, 0).unwrap()),
+        };
+
+        // The bot was down on Wednesday and restarts on Thursday
+        let thursday = Utc.with_ymd_and_hms(2026, 10, 22, 10, 0, 0).unwrap();
+        assert!(chat.is_due(thursday));
+        assert!(chat.is_due(Utc.with_ymd_and_hms(2026, 10, 25, 20, 0, 0).unwrap()));
+
+        chat.last_toad_at = Some(thursday);
+        assert!(!chat.is_due(thursday + Duration::hours(1)));
+
+        // Monday in Moscow is the next week, its Wednesday is not there yet
+        chat.last_toad_at = Some(Utc.with_ymd_and_hms(2026, 10, 14, 6, 0, 0).unwrap());
+        assert!(!chat.is_due(Utc.with_ymd_and_hms(2026, 10, 25, 22, 0, 0).unwrap()));
+
+        // A toad forced on Monday doesn't count for Wednesday
+        chat.last_toad_at = Some(Utc.with_ymd_and_hms(2026, 10, 19, 10, 0, 0).unwrap());
+        assert!(chat.is_due(thursday));
+    }
+
+    #[test]
+    fn campaign_is_the_week_wednesday() {
+        let chat = ToadChat {
+            chat_id: 1,
+            timezone: "Europe/Moscow".to_owned(),
+            toad_time: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            last_toad_at: None,
+        };
+        // Wednesday, Thursday and Sunday of the same week
+        for day in [21, 22, 25] {
+            let now = Utc.with_ymd_and_hms(2026, 10, day, 12, 0, 0).unwrap();
+            assert_eq!(chat.campaign(now), "toad-2026-10-21");
+        }
+        // Forced on Monday, before the week's toad
+        let monday = Utc.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap();
+        assert_eq!(
+            chat.campaign(monday),
+            format!("toad-manual-{}", monday.timestamp())
+        );
+        // Late Tuesday UTC is Wednesday in Moscow
+        let now = Utc.with_ymd_and_hms(2026, 10, 20, 22, 0, 0).unwrap();
+        assert_eq!(chat.campaign(now), "toad-2026-10-21");
+    }
 }
@@ -0,0 +1,89 @@
+use std::convert::Infallible;
+
+use anyhow::Result;
+use teloxide::prelude::*;
+use teloxide::update_listeners::{webhooks, UpdateListener};
+
+use crate::config::WebhookCfg;
+
+/// Listener of updates posted by Telegram, served on `cfg.addr`.
+///
+/// The webhook is not deleted on stop: other replicas behind the same URL keep
+/// receiving updates while this one restarts.
+pub async fn listener(bot: &Bot, cfg: WebhookCfg) -> Result<impl UpdateListener<Err = Infallible>> {
+    if cfg.register {
+        bot.set_webhook(cfg.url.clone())
+            .secret_token(cfg.secret.clone())
+            .await?;
+        tracing::info!("Webhook is set to {}", cfg.url);
+    }
+
+    let options = webhooks::Options::new(cfg.addr, cfg.url).secret_token(cfg.secret);
+    let (listener, stop, router) = webhooks::axum_no_setup(options);
+
+    let tcp_listener = tokio::net::TcpListener::bind(cfg.addr).await?;
+    tracing::info!("Webhook listener on {}", cfg.addr);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(tcp_listener, router)
+            .with_graceful_shutdown(stop)
+            .await
+        {
+            tracing::error!("Webhook server stopped: {}", e);
+        }
+    });
+
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use teloxide::types::UpdateKind;
+    use teloxide::update_listeners::AsUpdateStream;
+
+    const UPDATE: &str = r#"{
+        "update_id": 10000,
+        "message": {
+            "message_id": 1,
+            "date": 1792648800,
+            "chat": {"id": 42, "type": "private", "first_name": "Toad"},
+            "from": {"id": 42, "is_bot": false, "first_name": "Toad"},
+            "text": "/help"
+        }
+    }"#;
+
+    #[tokio::test]
+    async fn accepts_posted_updates_with_secret() {
+        let probe = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = probe.local_addr().unwrap();
+        drop(probe);
+
+        let cfg = WebhookCfg {
+            url: "https://bot.example.com/telegram".parse().unwrap(),
+            addr,
+            secret: "secret".to_owned(),
+            register: false,
+        };
+        let mut listener = listener(&Bot::new("0:token"), cfg).await.unwrap();
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/telegram", addr);
+        let post = |secret: &'static str| {
+            client
+                .post(&url)
+                .header("X-Telegram-Bot-Api-Secret-Token", secret)
+                .header("Content-Type", "application/json")
+                .body(UPDATE)
+                .send()
+        };
+        assert_eq!(post("wrong").await.unwrap().status(), 401);
+        assert_eq!(post("secret").await.unwrap().status(), 200);
+
+        let stream = listener.as_stream();
+        futures::pin_mut!(stream);
+        let update = stream.next().await.unwrap().unwrap();
+        assert_eq!(update.id.0, 10000);
+        assert!(matches!(update.kind, UpdateKind::Message(_)));
+    }
+}